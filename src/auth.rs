@@ -0,0 +1,289 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use librespot::core::{keymaster, session::Session};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::config as Config;
+use crate::history as History;
+use crate::interact::println;
+use crate::model::{OAuthTokenModel, TokenResponseModel};
+
+pub const TOKEN_FILE: &str = "oauth.json";
+const AUTHORIZE_ENDPOINT: &str = "https://accounts.spotify.com/authorize";
+const TOKEN_ENDPOINT: &str = "https://accounts.spotify.com/api/token";
+// Refresh tokens this long before they expire, so a request never goes out with a stale one
+const EXPIRY_MARGIN_SECS: i64 = 60;
+// How long to wait for the browser to come back before giving up on the login
+const REDIRECT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// How often to check for the redirect while waiting
+const REDIRECT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Browsers open speculative connections that never send a request
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn token_path() -> PathBuf {
+    let mut path = Config::data_path();
+    path.push(TOKEN_FILE);
+    path
+}
+
+fn redirect_uri() -> String {
+    format!(
+        "http://127.0.0.1:{}/callback",
+        Config::settings().redirect_port
+    )
+}
+
+/// A Web API access token: from the PKCE flow when a client ID is configured, otherwise
+/// from librespot's keymaster
+pub async fn access_token(session: &Session) -> Result<String, String> {
+    let client_id = match &Config::settings().client_id {
+        Some(client_id) => client_id,
+        None => {
+            return keymaster::get_token(session, Config::CLIENT_ID, Config::SCOPES)
+                .await
+                .map(|token| token.access_token)
+                .map_err(|_| String::from("Couldn't get a token from the keymaster"))
+        }
+    };
+    let saved = load();
+    if let Some(token) = &saved {
+        if token.expires_at > History::now() + EXPIRY_MARGIN_SECS {
+            return Ok(token.access_token.to_owned());
+        }
+        match refresh(client_id, &token.refresh_token).await {
+            Ok(token) => return Ok(token.access_token),
+            Err(err) => println(&format!("Couldn't refresh the access token: {}", err)),
+        }
+    }
+    authorize(client_id).await.map(|token| token.access_token)
+}
+
+/// Runs the PKCE flow up front if a client ID is configured and no token is saved yet
+pub async fn authorize_if_needed() -> Result<(), String> {
+    match &Config::settings().client_id {
+        Some(client_id) if load().is_none() => authorize(client_id).await.map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Asks the user to allow access in the browser and trades the code for tokens
+async fn authorize(client_id: &str) -> Result<OAuthTokenModel, String> {
+    let verifier = random_string(64);
+    let state = random_string(16);
+    let listener = TcpListener::bind(("127.0.0.1", Config::settings().redirect_port))
+        .map_err(|err| format!("Couldn't listen for the redirect: {}", err))?;
+    let authorize_url = reqwest::Url::parse_with_params(
+        AUTHORIZE_ENDPOINT,
+        &[
+            ("client_id", client_id),
+            ("response_type", "code"),
+            ("redirect_uri", &redirect_uri()),
+            ("scope", &Config::SCOPES.replace(',', " ")),
+            ("state", &state),
+            ("code_challenge_method", "S256"),
+            ("code_challenge", &code_challenge(&verifier)),
+        ],
+    )
+    .map_err(|err| err.to_string())?;
+    println(&format!(
+        "Open this link within {} minutes to allow access, then come back here:",
+        REDIRECT_TIMEOUT.as_secs() / 60
+    ));
+    println(authorize_url.as_str());
+    let (code, returned_state) = tokio::task::spawn_blocking(move || wait_for_redirect(listener))
+        .await
+        .map_err(|err| err.to_string())??;
+    if returned_state != state {
+        return Err(String::from("The redirect didn't come from this login"));
+    }
+    let redirect_uri = redirect_uri();
+    let params = [
+        ("grant_type", "authorization_code"),
+        ("code", &code),
+        ("redirect_uri", &redirect_uri),
+        ("client_id", client_id),
+        ("code_verifier", &verifier),
+    ];
+    let response = request_token(&params).await?;
+    let refresh_token = response.refresh_token.clone().unwrap_or_default();
+    save_response(response, refresh_token)
+}
+
+async fn refresh(client_id: &str, refresh_token: &str) -> Result<OAuthTokenModel, String> {
+    let params = [
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", client_id),
+    ];
+    let response = request_token(&params).await?;
+    let refresh_token = response
+        .refresh_token
+        .clone()
+        .unwrap_or_else(|| refresh_token.to_string());
+    save_response(response, refresh_token)
+}
+
+async fn request_token(params: &[(&str, &str)]) -> Result<TokenResponseModel, String> {
+    let response = reqwest::Client::new()
+        .post(TOKEN_ENDPOINT)
+        .form(params)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?;
+    response
+        .json::<TokenResponseModel>()
+        .await
+        .map_err(|err| err.to_string())
+}
+
+fn save_response(
+    response: TokenResponseModel,
+    refresh_token: String,
+) -> Result<OAuthTokenModel, String> {
+    let token = OAuthTokenModel {
+        access_token: response.access_token,
+        refresh_token,
+        expires_at: History::now() + response.expires_in,
+    };
+    save(&token).map_err(|err| format!("Couldn't save the token: {}", err))?;
+    Ok(token)
+}
+
+/// Writes the token readable only by the user, since the refresh token doesn't expire
+fn save(token: &OAuthTokenModel) -> io::Result<()> {
+    let path = token_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // `mode` only applies to new files, and older versions wrote it with the default umask
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(serde_json::to_string(token)?.as_bytes())
+}
+
+fn load() -> Option<OAuthTokenModel> {
+    let contents = fs::read_to_string(token_path()).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// Waits for the browser to come back to the loopback listener, returning the code and state,
+/// or gives up after `REDIRECT_TIMEOUT`
+fn wait_for_redirect(listener: TcpListener) -> Result<(String, String), String> {
+    let deadline = Instant::now() + REDIRECT_TIMEOUT;
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| err.to_string())?;
+    loop {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(String::from("Gave up waiting for the browser to log in"));
+                }
+                thread::sleep(REDIRECT_POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => return Err(err.to_string()),
+        };
+        let mut request_line = String::new();
+        let read = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(REQUEST_READ_TIMEOUT)))
+            .and_then(|_| BufReader::new(&stream).read_line(&mut request_line));
+        if read.is_err() {
+            continue;
+        }
+        // Browsers also ask for things like /favicon.ico
+        let result = match parse_redirect(&request_line) {
+            Some(result) => result,
+            None => continue,
+        };
+        let message = match &result {
+            Ok(_) => "Logged in, you can close this tab.",
+            Err(_) => "Login failed, see the terminal for details.",
+        };
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            message.len(),
+            message
+        );
+        return result;
+    }
+}
+
+/// Reads the code and state out of a `GET /callback?...` request line, or `None` if the
+/// request is for some other path
+fn parse_redirect(request_line: &str) -> Option<Result<(String, String), String>> {
+    let target = request_line.split_whitespace().nth(1)?;
+    let url = reqwest::Url::parse(&format!("http://127.0.0.1{}", target)).ok()?;
+    if url.path() != "/callback" {
+        return None;
+    }
+    let query_value = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.to_string())
+    };
+    if let Some(error) = query_value("error") {
+        return Some(Err(format!("Access was not allowed: {}", error)));
+    }
+    match (query_value("code"), query_value("state")) {
+        (Some(code), Some(state)) => Some(Ok((code, state))),
+        _ => Some(Err(String::from("The redirect had no code in it"))),
+    }
+}
+
+fn code_challenge(verifier: &str) -> String {
+    let digest = Sha256::digest(verifier.as_bytes());
+    base64::encode_config(digest, base64::URL_SAFE_NO_PAD)
+}
+
+fn random_string(length: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_challenge_is_the_unpadded_base64url_sha256_of_the_verifier() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mJ92IgRP5r_c-Bpqv-U_WxzTSjG3e8"),
+            "dgilPRQ_D75-uOt9g-_Wj7a4WWtq5jDc5ctTxAqV1k8"
+        );
+    }
+
+    #[test]
+    fn parses_the_redirect_request_line() {
+        assert_eq!(
+            parse_redirect("GET /callback?code=abc%2B1&state=xyz HTTP/1.1\r\n"),
+            Some(Ok((String::from("abc+1"), String::from("xyz"))))
+        );
+        assert_eq!(parse_redirect("GET /favicon.ico HTTP/1.1\r\n"), None);
+        assert!(matches!(
+            parse_redirect("GET /callback?error=access_denied&state=xyz HTTP/1.1\r\n"),
+            Some(Err(_))
+        ));
+    }
+}
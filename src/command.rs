@@ -20,8 +20,24 @@ impl Command {
 pub enum CommandType {
     Play,
     Pause,
+    Show,
+    Now,
+    Artist,
+    History,
+    Resume,
+    Playlist,
+    Shuffle,
+    Like,
+    Unlike,
+    Save,
+    Unsave,
     Stop,
+    Repeat,
     List,
+    Config,
+    Switch,
+    Login,
+    Logout,
     Whoami,
     Help,
     Quit,
@@ -34,8 +50,24 @@ impl std::str::FromStr for CommandType {
         match s {
             "play" => Ok(CommandType::Play),
             "pause" => Ok(CommandType::Pause),
+            "show" => Ok(CommandType::Show),
+            "now" | "status" => Ok(CommandType::Now),
+            "artist" => Ok(CommandType::Artist),
+            "history" => Ok(CommandType::History),
+            "resume" => Ok(CommandType::Resume),
+            "playlist" => Ok(CommandType::Playlist),
+            "shuffle" => Ok(CommandType::Shuffle),
+            "like" => Ok(CommandType::Like),
+            "unlike" => Ok(CommandType::Unlike),
+            "save" => Ok(CommandType::Save),
+            "unsave" => Ok(CommandType::Unsave),
             "stop" => Ok(CommandType::Stop),
+            "repeat" => Ok(CommandType::Repeat),
             "ls" | "list" => Ok(CommandType::List),
+            "config" => Ok(CommandType::Config),
+            "switch" => Ok(CommandType::Switch),
+            "login" => Ok(CommandType::Login),
+            "logout" => Ok(CommandType::Logout),
             "whoami" => Ok(CommandType::Whoami),
             "help" | "man" => Ok(CommandType::Help),
             "quit" => Ok(CommandType::Quit),
@@ -43,9 +75,3 @@ impl std::str::FromStr for CommandType {
         }
     }
 }
-
-#[derive(Debug)]
-pub struct CommandOk {}
-
-#[derive(Debug)]
-pub struct CommandError {}
@@ -1,3 +1,349 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::{env, fmt, fs, io};
+
+use crate::play::Repeat;
+use crate::profile as Profile;
+
 pub const CLIENT_ID: &str = "a46e4576183f4cdea53ff1c559deb4a9";
-pub const APP_NAME:&str = "spotifycli";
-pub const SCOPES: &str = "playlist-read-private,user-library-read";
\ No newline at end of file
+pub const APP_NAME: &str = "spotifycli";
+pub const SCOPES: &str = "playlist-read-private,playlist-modify-private,playlist-modify-public,user-library-read,user-library-modify,user-follow-read,user-read-playback-position";
+
+const CONFIG_FILE: &str = "config.toml";
+// Prefix of the environment variables that override the config file, e.g. SPOTIFYCLI_VOLUME
+const ENV_PREFIX: &str = "SPOTIFYCLI_";
+const COLOURS: &[&str] = &[
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// Every setting, in the order `config get` prints them. Dots are tables in the config file.
+pub const KEYS: &[&str] = &[
+    "shuffle",
+    "repeat",
+    "volume",
+    "bitrate",
+    "backend",
+    "device",
+    "theme.accent",
+    "theme.warning",
+    "cache_dir",
+    "api_base_url",
+    "client_id",
+    "redirect_port",
+    "profile",
+];
+
+/// Preferences, resolved once at startup from the command line, environment, config file
+/// and defaults, in that order
+pub struct Settings {
+    pub shuffle: bool,
+    pub repeat: Repeat,
+    // Percent
+    pub volume: u8,
+    // kbps: 96, 160 or 320
+    pub bitrate: u16,
+    // `None` picks librespot's default audio backend and device
+    pub backend: Option<String>,
+    pub device: Option<String>,
+    pub theme_accent: String,
+    pub theme_warning: String,
+    // Where credentials, history and the saved session are kept
+    pub cache_dir: PathBuf,
+    pub api_base_url: String,
+    // Your own app's client ID, which switches Web API tokens from librespot's keymaster
+    // to the OAuth PKCE flow
+    pub client_id: Option<String>,
+    // Port of the loopback listener that receives the OAuth redirect
+    pub redirect_port: u16,
+    // Profile to start with, see `profile`
+    pub profile: String,
+}
+
+/// Where a setting's value came from, highest precedence first
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Source {
+    CommandLine,
+    Environment,
+    File,
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let source = match self {
+            Source::CommandLine => "command line",
+            Source::Environment => "environment",
+            Source::File => "config file",
+            Source::Default => "default",
+        };
+        write!(f, "{}", source)
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+pub fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(|| {
+        let args: Vec<String> = env::args().collect();
+        let file = load_file();
+        let value = |key: &str| resolve(key, &args, &|name| env::var(name).ok(), &file).0;
+        Settings {
+            shuffle: value("shuffle") == "true",
+            repeat: value("repeat").parse().unwrap_or(Repeat::Off),
+            volume: value("volume").parse().unwrap_or(100),
+            bitrate: value("bitrate").parse().unwrap_or(320),
+            backend: Some(value("backend")).filter(|backend| backend != "default"),
+            device: Some(value("device")).filter(|device| device != "default"),
+            theme_accent: value("theme.accent"),
+            theme_warning: value("theme.warning"),
+            cache_dir: PathBuf::from(value("cache_dir")),
+            api_base_url: value("api_base_url"),
+            client_id: Some(value("client_id")).filter(|client_id| client_id != CLIENT_ID),
+            redirect_port: value("redirect_port").parse().unwrap_or(8898),
+            profile: value("profile"),
+        }
+    })
+}
+
+/// Where the active profile's credentials and other app data are kept
+pub fn data_path() -> PathBuf {
+    Profile::path(&Profile::active())
+}
+
+pub fn config_path() -> PathBuf {
+    let mut config_path = dirs::config_dir().unwrap();
+    config_path.push(APP_NAME);
+    config_path.push(CONFIG_FILE);
+    config_path
+}
+
+/// The value of `key` and where it came from. Values that don't validate are warned about
+/// and skipped in favour of the next source.
+pub fn resolve(
+    key: &str,
+    args: &[String],
+    env_var: &dyn Fn(&str) -> Option<String>,
+    file: &toml::Table,
+) -> (String, Source) {
+    let flag = format!("--{}", key);
+    let from_args = args
+        .iter()
+        .position(|arg| *arg == flag)
+        .and_then(|index| args.get(index + 1).cloned());
+    let env_name = ENV_PREFIX.to_string() + &key.replace('.', "_").to_uppercase();
+    let candidates = [
+        (from_args, Source::CommandLine),
+        (env_var(&env_name), Source::Environment),
+        (file_value(file, key), Source::File),
+    ];
+    for (value, source) in candidates {
+        if let Some(value) = value {
+            match validate(key, &value) {
+                Ok(value) => return (value, source),
+                Err(err) => eprintln!("Ignoring {} from the {}: {}", key, source, err),
+            }
+        }
+    }
+    (default_value(key), Source::Default)
+}
+
+/// Checks `value` for `key` and returns it in its usual form
+pub fn validate(key: &str, value: &str) -> Result<String, String> {
+    let value = value.trim();
+    match key {
+        "shuffle" => match value {
+            "true" | "on" => Ok(String::from("true")),
+            "false" | "off" => Ok(String::from("false")),
+            _ => Err(String::from("expected true or false")),
+        },
+        "repeat" => value.parse::<Repeat>().map(|repeat| repeat.to_string()),
+        "volume" => match value.parse::<u8>() {
+            Ok(volume) if volume <= 100 => Ok(volume.to_string()),
+            _ => Err(String::from("expected a percentage from 0 to 100")),
+        },
+        "bitrate" => match value {
+            "96" | "160" | "320" => Ok(value.to_string()),
+            _ => Err(String::from("expected 96, 160 or 320")),
+        },
+        "backend" | "device" | "cache_dir" if value.is_empty() => {
+            Err(String::from("can't be empty"))
+        }
+        "backend" | "device" | "cache_dir" => Ok(value.to_string()),
+        "theme.accent" | "theme.warning" => match COLOURS.contains(&value) {
+            true => Ok(value.to_string()),
+            false => Err(format!("expected one of {}", COLOURS.join(", "))),
+        },
+        "api_base_url" => match value.starts_with("https://") || value.starts_with("http://") {
+            true => Ok(value.trim_end_matches('/').to_string()),
+            false => Err(String::from("expected an http(s) URL")),
+        },
+        "client_id" => match value.len() == 32 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+            true => Ok(value.to_lowercase()),
+            false => Err(String::from("expected a 32 character hex client ID")),
+        },
+        "redirect_port" => match value.parse::<u16>() {
+            Ok(port) if port > 0 => Ok(port.to_string()),
+            _ => Err(String::from("expected a port number")),
+        },
+        "profile" => match Profile::is_valid_name(value) {
+            true => Ok(value.to_string()),
+            false => Err(String::from("expected letters, digits, - and _ only")),
+        },
+        _ => Err(format!("unknown setting {}", key)),
+    }
+}
+
+fn default_value(key: &str) -> String {
+    match key {
+        "shuffle" => String::from("false"),
+        "repeat" => Repeat::Off.to_string(),
+        "volume" => String::from("100"),
+        "bitrate" => String::from("320"),
+        "backend" | "device" => String::from("default"),
+        "theme.accent" => String::from("green"),
+        "theme.warning" => String::from("yellow"),
+        "cache_dir" => {
+            let mut local_data_path = dirs::data_local_dir().unwrap();
+            local_data_path.push(APP_NAME);
+            local_data_path.to_string_lossy().to_string()
+        }
+        "api_base_url" => String::from("https://api.spotify.com/v1"),
+        "client_id" => String::from(CLIENT_ID),
+        "redirect_port" => String::from("8898"),
+        "profile" => String::from(Profile::DEFAULT_PROFILE),
+        _ => String::new(),
+    }
+}
+
+/// The config file's contents, or nothing if it is missing or can't be parsed
+pub fn load_file() -> toml::Table {
+    let contents = match fs::read_to_string(config_path()) {
+        Ok(contents) => contents,
+        Err(_) => return toml::Table::new(),
+    };
+    contents.parse().unwrap_or_else(|err| {
+        eprintln!("Ignoring {}: {}", config_path().display(), err);
+        toml::Table::new()
+    })
+}
+
+fn file_value(file: &toml::Table, key: &str) -> Option<String> {
+    let (table, name) = match key.split_once('.') {
+        Some((table, name)) => (file.get(table)?.as_table()?, name),
+        None => (file, key),
+    };
+    match table.get(name)? {
+        toml::Value::String(value) => Some(value.to_owned()),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Validates `value` and writes it to the config file, keeping everything else in it
+pub fn save_setting(key: &str, value: &str) -> Result<(), String> {
+    let value = validate(key, value)?;
+    let value = match key {
+        "shuffle" => toml::Value::Boolean(value == "true"),
+        "volume" | "bitrate" | "redirect_port" => {
+            toml::Value::Integer(value.parse().unwrap_or_default())
+        }
+        _ => toml::Value::String(value),
+    };
+    let mut file = load_file();
+    match key.split_once('.') {
+        Some((table, name)) => {
+            let table = file
+                .entry(table)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            match table.as_table_mut() {
+                Some(table) => table.insert(name.to_string(), value),
+                None => return Err(format!("{} in the config file isn't a table", key)),
+            };
+        }
+        None => {
+            file.insert(key.to_string(), value);
+        }
+    }
+    let contents = toml::to_string(&file).map_err(|err| err.to_string())?;
+    write_file(&contents).map_err(|err| err.to_string())
+}
+
+fn write_file(contents: &str) -> io::Result<()> {
+    let path = config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file() -> toml::Table {
+        "volume = 40\nrepeat = \"all\"\n[theme]\naccent = \"cyan\"\n"
+            .parse()
+            .unwrap()
+    }
+
+    fn env_var(name: &str) -> Option<String> {
+        match name {
+            "SPOTIFYCLI_VOLUME" => Some(String::from("60")),
+            "SPOTIFYCLI_BITRATE" => Some(String::from("128")),
+            _ => None,
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn command_line_beats_environment_beats_file_beats_default() {
+        let cli = args(&["spotifycli", "--volume", "80"]);
+        assert_eq!(
+            resolve("volume", &cli, &env_var, &file()),
+            (String::from("80"), Source::CommandLine)
+        );
+        assert_eq!(
+            resolve("volume", &[], &env_var, &file()),
+            (String::from("60"), Source::Environment)
+        );
+        assert_eq!(
+            resolve("repeat", &[], &env_var, &file()),
+            (String::from("all"), Source::File)
+        );
+        assert_eq!(
+            resolve("theme.accent", &[], &env_var, &file()),
+            (String::from("cyan"), Source::File)
+        );
+        assert_eq!(
+            resolve("shuffle", &[], &env_var, &file()),
+            (String::from("false"), Source::Default)
+        );
+    }
+
+    #[test]
+    fn invalid_values_fall_through_to_the_next_source() {
+        assert_eq!(
+            resolve("bitrate", &[], &env_var, &file()),
+            (String::from("320"), Source::Default)
+        );
+        let cli = args(&["spotifycli", "--repeat", "twice"]);
+        assert_eq!(
+            resolve("repeat", &cli, &env_var, &file()),
+            (String::from("all"), Source::File)
+        );
+    }
+
+    #[test]
+    fn validates_and_normalises_values() {
+        assert_eq!(validate("shuffle", "on"), Ok(String::from("true")));
+        assert!(validate("volume", "101").is_err());
+        assert!(validate("theme.warning", "chartreuse").is_err());
+        assert_eq!(
+            validate("api_base_url", "http://localhost:8080/v1/"),
+            Ok(String::from("http://localhost:8080/v1"))
+        );
+        assert!(validate("colour", "red").is_err());
+    }
+}
@@ -1,87 +1,633 @@
 use std::collections::HashMap;
 
+use reqwest::Method;
+use serde_json::json;
+
 use crate::{
-    config as Config,
-    model::{AlbumsModel, PlaylistsModel},
+    auth as Auth, config as Config,
+    interact::{print_warning, println},
+    model::{
+        AlbumsModel, ArtistAlbumModel, ArtistAlbumsModel, ArtistModel, ArtistsModel, EpisodeModel,
+        PlaylistModel, PlaylistsModel, RelatedArtistsModel, ShowsModel, SnapshotModel,
+    },
+    play::format_artists,
 };
+use futures::future::join_all;
+use futures::stream::{self, StreamExt};
 use librespot::core::{
-    keymaster::{self, Token},
     session::Session,
     spotify_id::{SpotifyId, SpotifyIdError},
 };
-use librespot::metadata::{Album, Metadata, Playlist};
+use librespot::metadata::{Album, Artist, Metadata, Playlist, Show};
 
 pub struct Fetcher {
-    playlists: HashMap<String, Playlist>,
-    albums: HashMap<String, Album>,
+    // Keyed by id, since names aren't unique
+    playlists: HashMap<String, LibraryPlaylist>,
+    albums: HashMap<String, LibraryAlbum>,
+    artists: HashMap<String, ArtistModel>,
+    shows: HashMap<String, Show>,
 }
 
 impl Fetcher {
     pub async fn new(session: &Session) -> Result<Fetcher, Box<dyn std::error::Error>> {
         let api_client = reqwest::Client::new();
-        let mut playlists: HashMap<String, Playlist> = HashMap::new();
-        let mut albums: HashMap<String, Album> = HashMap::new();
+        let mut playlists: HashMap<String, LibraryPlaylist> = HashMap::new();
+        let mut albums: HashMap<String, LibraryAlbum> = HashMap::new();
         let token = fetch_token(session).await;
 
         // Get user's playlists
-        let playlists_endpoint =
-            String::from("https://api.spotify.com/v1/me/playlists?fields=items(id)");
-        let playlists_json = request(&api_client, playlists_endpoint, &token)
+        let playlists_endpoint = format!("{}/me/playlists?limit=50", api_base_url());
+        let playlists_json = request(&api_client, Method::GET, playlists_endpoint, &token, None)
             .await
             .unwrap();
         let fetched_playlists = serde_json::from_str::<PlaylistsModel>(playlists_json.as_str())?;
-        for p in fetched_playlists.items {
-            let playlist = fetch_individual::<Playlist>(p.id, session).await.unwrap();
-            playlists.insert(playlist.name.to_owned(), playlist);
+        // The Web API lists the most recently added first
+        let playlist_count = fetched_playlists.items.len();
+        for (index, p) in fetched_playlists.items.into_iter().enumerate() {
+            let playlist = fetch_individual::<Playlist>(p.id.to_owned(), session)
+                .await
+                .unwrap();
+            let added = playlist_count - index;
+            let library_playlist = LibraryPlaylist::new(p, playlist, &session.username(), added);
+            playlists.insert(library_playlist.id.to_owned(), library_playlist);
         }
 
         // Get user's albums
-        let albums_endpoint =
-            String::from("https://api.spotify.com/v1/me/albums?fields=items(album(id))"); // if only this worked...
-        let albums_json = request(&api_client, albums_endpoint, &token).await.unwrap();
+        let albums_endpoint = format!("{}/me/albums?fields=items(album(id))", api_base_url()); // if only this worked...
+        let albums_json = request(&api_client, Method::GET, albums_endpoint, &token, None)
+            .await
+            .unwrap();
         let fetched_albums = serde_json::from_str::<AlbumsModel>(albums_json.as_str())?;
-        for album_wrapper in fetched_albums.items {
+        let album_count = fetched_albums.items.len();
+        for (index, album_wrapper) in fetched_albums.items.into_iter().enumerate() {
             let album = fetch_individual::<Album>(album_wrapper.album.id, session)
                 .await
                 .unwrap();
-            let album_key = album.name.to_owned() + " - " + &album_wrapper.album.artists[0].name;
-            albums.insert(album_key, album);
+            let artist_names: Vec<String> = album_wrapper
+                .album
+                .artists
+                .iter()
+                .map(|artist| artist.name.to_owned())
+                .collect();
+            let library_album = LibraryAlbum {
+                artists: artist_names,
+                added: album_count - index,
+                album,
+            };
+            albums.insert(library_album.key(), library_album);
         }
 
-        let fetcher = Fetcher { playlists, albums };
+        // Get user's followed artists, which are only a nice-to-have
+        let artists: HashMap<String, ArtistModel> =
+            match fetch_followed_artists(&api_client, &token).await {
+                Ok(followed) => followed
+                    .into_iter()
+                    .map(|artist| (artist.name.to_owned(), artist))
+                    .collect(),
+                Err(err) => {
+                    print_warning(&format!("Couldn't load followed artists: {}", err));
+                    HashMap::new()
+                }
+            };
+
+        // Get user's saved podcasts, which are only a nice-to-have
+        let shows = match fetch_saved_shows(&api_client, &token, session).await {
+            Ok(shows) => shows,
+            Err(err) => {
+                print_warning(&format!("Couldn't load saved podcasts: {}", err));
+                HashMap::new()
+            }
+        };
+
+        let fetcher = Fetcher {
+            playlists,
+            albums,
+            artists,
+            shows,
+        };
         Ok(fetcher)
     }
 
-    pub fn playlists(&self) -> &HashMap<String, Playlist> {
+    pub fn playlists(&self) -> &HashMap<String, LibraryPlaylist> {
         &self.playlists
     }
 
-    pub fn albums(&self) -> &HashMap<String, Album> {
+    /// Playlists under the names shown to the user. Playlists of other users get their
+    /// owner added, and any names that still clash are numbered in id order.
+    pub fn playlists_by_label(&self) -> HashMap<String, &LibraryPlaylist> {
+        let mut playlists: Vec<&LibraryPlaylist> = self.playlists.values().collect();
+        playlists.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut labelled: HashMap<String, &LibraryPlaylist> = HashMap::new();
+        for playlist in playlists {
+            let label = playlist.label();
+            let mut unique_label = label.to_owned();
+            let mut count = 1;
+            while labelled.contains_key(&unique_label) {
+                count += 1;
+                unique_label = format!("{} ({})", label, count);
+            }
+            labelled.insert(unique_label, playlist);
+        }
+        labelled
+    }
+
+    pub fn albums(&self) -> &HashMap<String, LibraryAlbum> {
         &self.albums
     }
+
+    pub fn artists(&self) -> &HashMap<String, ArtistModel> {
+        &self.artists
+    }
+
+    pub fn shows(&self) -> &HashMap<String, Show> {
+        &self.shows
+    }
+
+    /// Creates an empty private playlist called `name` and adds it to the library
+    pub async fn create_playlist(
+        &mut self,
+        session: &Session,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let api_client = reqwest::Client::new();
+        let token = fetch_token(session).await;
+        let create_endpoint = format!("{}/users/{}/playlists", api_base_url(), session.username());
+        let body = json!({ "name": name, "public": false });
+        let created_json = request(
+            &api_client,
+            Method::POST,
+            create_endpoint,
+            &token,
+            Some(body),
+        )
+        .await?;
+        let created = serde_json::from_str::<PlaylistModel>(created_json.as_str())?;
+        let playlist = Playlist {
+            revision: Vec::new(),
+            user: session.username(),
+            name: created.name.to_owned(),
+            tracks: Vec::new(),
+        };
+        let added = self.playlists.values().map(|p| p.added).max().unwrap_or(0) + 1;
+        let library_playlist = LibraryPlaylist::new(created, playlist, &session.username(), added);
+        self.playlists
+            .insert(library_playlist.id.to_owned(), library_playlist);
+        Ok(())
+    }
+
+    /// Appends `track_id` to the end of the playlist with id `playlist_id`
+    pub async fn add_to_playlist(
+        &mut self,
+        session: &Session,
+        playlist_id: &str,
+        track_id: SpotifyId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let api_client = reqwest::Client::new();
+        let token = fetch_token(session).await;
+        let uri = track_id.to_uri().map_err(|_| "Invalid track id")?;
+        let body = json!({ "uris": [uri] });
+        let endpoint = self.playlist_items_endpoint(playlist_id)?;
+        let snapshot_json =
+            request(&api_client, Method::POST, endpoint, &token, Some(body)).await?;
+        if let Some(library_playlist) = self.playlists.get_mut(playlist_id) {
+            library_playlist.set_snapshot(&snapshot_json);
+            library_playlist.playlist.tracks.push(track_id);
+        }
+        Ok(())
+    }
+
+    /// Removes the track at index `position` of the playlist with id `playlist_id`, leaving
+    /// any other copies of it alone
+    pub async fn remove_from_playlist(
+        &mut self,
+        session: &Session,
+        playlist_id: &str,
+        position: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let library_playlist = self.playlists.get(playlist_id).ok_or("No such playlist")?;
+        if position >= library_playlist.playlist.tracks.len() {
+            return Err("No track at that position".into());
+        }
+        let api_client = reqwest::Client::new();
+        let token = fetch_token(session).await;
+        let body = json!({
+            "positions": [position],
+            "snapshot_id": library_playlist.snapshot_id,
+        });
+        let endpoint = self.playlist_items_endpoint(playlist_id)?;
+        let snapshot_json =
+            request(&api_client, Method::DELETE, endpoint, &token, Some(body)).await?;
+        if let Some(library_playlist) = self.playlists.get_mut(playlist_id) {
+            library_playlist.set_snapshot(&snapshot_json);
+            library_playlist.playlist.tracks.remove(position);
+        }
+        Ok(())
+    }
+
+    /// Moves the track at index `from` of the playlist with id `playlist_id` so it ends up
+    /// at `to`
+    pub async fn move_in_playlist(
+        &mut self,
+        session: &Session,
+        playlist_id: &str,
+        from: usize,
+        to: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let length = self
+            .playlists
+            .get(playlist_id)
+            .ok_or("No such playlist")?
+            .playlist
+            .tracks
+            .len();
+        if from >= length || to >= length {
+            return Err("No track at that position".into());
+        }
+        let api_client = reqwest::Client::new();
+        let token = fetch_token(session).await;
+        let body = json!({
+            "range_start": from,
+            "range_length": 1,
+            "insert_before": insert_before(from, to),
+        });
+        let endpoint = self.playlist_items_endpoint(playlist_id)?;
+        let snapshot_json = request(&api_client, Method::PUT, endpoint, &token, Some(body)).await?;
+        if let Some(library_playlist) = self.playlists.get_mut(playlist_id) {
+            library_playlist.set_snapshot(&snapshot_json);
+            let tracks = &mut library_playlist.playlist.tracks;
+            let track_id = tracks.remove(from);
+            tracks.insert(to, track_id);
+        }
+        Ok(())
+    }
+
+    /// Adds `track_id` to the user's liked songs
+    pub async fn like_track(
+        &self,
+        session: &Session,
+        track_id: SpotifyId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        change_library(session, Method::PUT, "tracks", track_id).await
+    }
+
+    pub async fn unlike_track(
+        &self,
+        session: &Session,
+        track_id: SpotifyId,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        change_library(session, Method::DELETE, "tracks", track_id).await
+    }
+
+    /// Saves the album to the user's library and returns the name it is listed under
+    pub async fn save_album(
+        &mut self,
+        session: &Session,
+        album_id: SpotifyId,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        change_library(session, Method::PUT, "albums", album_id).await?;
+        let album = Album::get(session, album_id)
+            .await
+            .map_err(|_| "Album lookup failed")?;
+        let artists = join_all(album.artists.iter().map(|id| Artist::get(session, *id))).await;
+        let artist_names: Vec<String> = artists
+            .into_iter()
+            .filter_map(Result::ok)
+            .map(|artist| artist.name)
+            .collect();
+        let library_album = LibraryAlbum {
+            artists: artist_names,
+            added: self.albums.values().map(|a| a.added).max().unwrap_or(0) + 1,
+            album,
+        };
+        let key = library_album.key();
+        self.albums.insert(key.to_owned(), library_album);
+        Ok(key)
+    }
+
+    /// Removes the album listed as `name` from the user's library
+    pub async fn unsave_album(
+        &mut self,
+        session: &Session,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let album_id = self.albums.get(name).ok_or("No such album")?.album.id;
+        change_library(session, Method::DELETE, "albums", album_id).await?;
+        self.albums.remove(name);
+        Ok(())
+    }
+
+    fn playlist_items_endpoint(&self, playlist_id: &str) -> Result<String, &'static str> {
+        if !self.playlists.contains_key(playlist_id) {
+            return Err("No such playlist");
+        }
+        Ok(format!(
+            "{}/playlists/{}/tracks",
+            api_base_url(),
+            playlist_id
+        ))
+    }
+}
+
+/// Saves (PUT) or removes (DELETE) an item in the user's "tracks" or "albums"
+async fn change_library(
+    session: &Session,
+    method: Method,
+    kind: &str,
+    id: SpotifyId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let api_client = reqwest::Client::new();
+    let token = fetch_token(session).await;
+    let id = id.to_base62().map_err(|_| "Invalid id")?;
+    let endpoint = format!("{}/me/{}", api_base_url(), kind);
+    let body = json!({ "ids": [id] });
+    request(&api_client, method, endpoint, &token, Some(body)).await?;
+    Ok(())
+}
+
+/// The Web API counts `insert_before` in positions from before the move, so moving a track
+/// down the list has to aim one past where it should land
+fn insert_before(from: usize, to: usize) -> usize {
+    match to > from {
+        true => to + 1,
+        false => to,
+    }
+}
+
+/// A playlist in the library along with the owner details librespot leaves out
+pub struct LibraryPlaylist {
+    pub id: String,
+    pub owner: String,
+    // Whether the logged in user owns it
+    pub owned: bool,
+    pub collaborative: bool,
+    // Higher for playlists added to the library more recently
+    pub added: usize,
+    // Version of the playlist on Spotify, which removing by position has to name
+    pub snapshot_id: String,
+    pub playlist: Playlist,
 }
 
-pub async fn fetch_token(session: &Session) -> Token {
-    let token = keymaster::get_token(session, Config::CLIENT_ID, Config::SCOPES)
+impl LibraryPlaylist {
+    fn new(
+        model: PlaylistModel,
+        playlist: Playlist,
+        username: &str,
+        added: usize,
+    ) -> LibraryPlaylist {
+        LibraryPlaylist {
+            id: model.id,
+            added,
+            owned: model.owner.id == username,
+            owner: model.owner.display_name.unwrap_or(model.owner.id),
+            collaborative: model.collaborative,
+            snapshot_id: model.snapshot_id,
+            playlist,
+        }
+    }
+
+    // Keeps the snapshot in step after one of our own changes
+    fn set_snapshot(&mut self, snapshot_json: &str) {
+        if let Ok(snapshot) = serde_json::from_str::<SnapshotModel>(snapshot_json) {
+            self.snapshot_id = snapshot.snapshot_id;
+        }
+    }
+
+    /// "Name" for the user's own playlists, "Name (by owner)" for anyone else's
+    pub fn label(&self) -> String {
+        match self.owned {
+            true => self.playlist.name.to_owned(),
+            false => format!("{} (by {})", self.playlist.name, self.owner),
+        }
+    }
+}
+
+/// A saved album with the artist names it is listed under
+pub struct LibraryAlbum {
+    pub artists: Vec<String>,
+    // Higher for albums saved more recently
+    pub added: usize,
+    pub album: Album,
+}
+
+impl LibraryAlbum {
+    /// Name the album is listed under in the library, "Album - Artist, Artist"
+    pub fn key(&self) -> String {
+        self.album.name.to_owned() + " - " + &format_artists(&self.artists, &[])
+    }
+}
+
+/// An artist's top tracks and releases, looked up on demand since they aren't in the library
+pub struct ArtistCatalog {
+    pub name: String,
+    pub top_tracks: Vec<SpotifyId>,
+    // Oldest first
+    pub albums: Vec<ArtistAlbumModel>,
+    pub singles: Vec<ArtistAlbumModel>,
+    pub related: Vec<ArtistModel>,
+}
+
+impl ArtistCatalog {
+    /// Every track of the artist's albums and then singles, in release order
+    pub async fn discography(&self, session: &Session) -> Vec<SpotifyId> {
+        let releases = self.albums.iter().chain(self.singles.iter());
+        let fetched_albums: Vec<Option<Album>> = stream::iter(releases)
+            .map(|release| async move {
+                let album_id = SpotifyId::from_base62(&release.id).ok()?;
+                Album::get(session, album_id).await.ok()
+            })
+            .buffered(8)
+            .collect()
+            .await;
+        fetched_albums
+            .into_iter()
+            .flatten()
+            .flat_map(|album| album.tracks)
+            .collect()
+    }
+}
+
+/// Finds the best match for `name` among all artists on Spotify
+pub async fn search_artist(
+    session: &Session,
+    name: &str,
+) -> Result<Option<ArtistModel>, Box<dyn std::error::Error>> {
+    let api_client = reqwest::Client::new();
+    let token = fetch_token(session).await;
+    let search_endpoint = reqwest::Url::parse_with_params(
+        &format!("{}/search", api_base_url()),
+        &[("q", name), ("type", "artist"), ("limit", "1")],
+    )?;
+    let search_json = request(
+        &api_client,
+        Method::GET,
+        search_endpoint.to_string(),
+        &token,
+        None,
+    )
+    .await?;
+    let search_result = serde_json::from_str::<ArtistsModel>(search_json.as_str())?;
+    Ok(search_result.artists.items.into_iter().next())
+}
+
+pub async fn fetch_artist(
+    session: &Session,
+    artist_model: &ArtistModel,
+) -> Result<ArtistCatalog, Box<dyn std::error::Error>> {
+    let api_client = reqwest::Client::new();
+    let token = fetch_token(session).await;
+    let artist_id = SpotifyId::from_base62(&artist_model.id).map_err(|_| "Invalid artist id")?;
+    let artist = Artist::get(session, artist_id)
         .await
-        .unwrap();
-    token
+        .map_err(|_| "Artist lookup failed")?;
+
+    let mut releases = fetch_artist_releases(&api_client, &token, &artist_model.id).await?;
+    releases.sort_by(|a, b| a.release_date.cmp(&b.release_date));
+    let (albums, singles) = releases
+        .into_iter()
+        .partition(|release| release.album_group == "album");
+
+    // Spotify no longer serves related artists to every app, so they're left out if it won't
+    let related_endpoint = format!(
+        "{}/artists/{}/related-artists",
+        api_base_url(),
+        artist_model.id
+    );
+    let related = match request(&api_client, Method::GET, related_endpoint, &token, None).await {
+        Ok(related_json) => serde_json::from_str::<RelatedArtistsModel>(&related_json)
+            .map(|related| related.artists)
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+
+    Ok(ArtistCatalog {
+        name: artist.name,
+        top_tracks: artist.top_tracks,
+        albums,
+        singles,
+        related,
+    })
+}
+
+/// Every album and single by the artist with id `artist_id`, following `next` one page at
+/// a time
+async fn fetch_artist_releases(
+    api_client: &reqwest::Client,
+    token: &str,
+    artist_id: &str,
+) -> Result<Vec<ArtistAlbumModel>, Box<dyn std::error::Error>> {
+    let mut releases = Vec::new();
+    let mut albums_endpoint = Some(format!(
+        "{}/artists/{}/albums?include_groups=album,single&limit=50",
+        api_base_url(),
+        artist_id
+    ));
+    while let Some(endpoint) = albums_endpoint {
+        let albums_json = request(api_client, Method::GET, endpoint, token, None).await?;
+        let page = serde_json::from_str::<ArtistAlbumsModel>(albums_json.as_str())?;
+        releases.extend(page.items);
+        albums_endpoint = page.next;
+    }
+    Ok(releases)
+}
+
+/// Where the user left off in an episode, on any device. `None` if it hasn't been started
+/// or was listened to the end.
+pub async fn fetch_resume_position(
+    session: &Session,
+    episode_id: SpotifyId,
+) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+    let api_client = reqwest::Client::new();
+    let token = fetch_token(session).await;
+    let episode_id = episode_id.to_base62().map_err(|_| "Invalid episode id")?;
+    let episode_endpoint = format!("{}/episodes/{}", api_base_url(), episode_id);
+    let episode_json = request(&api_client, Method::GET, episode_endpoint, &token, None).await?;
+    let episode = serde_json::from_str::<EpisodeModel>(episode_json.as_str())?;
+    let resume_position = episode
+        .resume_point
+        .filter(|resume_point| !resume_point.fully_played)
+        .map(|resume_point| resume_point.resume_position_ms)
+        .filter(|position_ms| *position_ms > 0);
+    Ok(resume_position)
+}
+
+/// Every artist the user follows, following the cursor one page at a time
+async fn fetch_followed_artists(
+    api_client: &reqwest::Client,
+    token: &str,
+) -> Result<Vec<ArtistModel>, Box<dyn std::error::Error>> {
+    let mut artists = Vec::new();
+    let mut after: Option<String> = None;
+    loop {
+        let mut following_endpoint =
+            format!("{}/me/following?type=artist&limit=50", api_base_url());
+        if let Some(after) = &after {
+            following_endpoint += &format!("&after={}", after);
+        }
+        let following_json =
+            request(api_client, Method::GET, following_endpoint, token, None).await?;
+        let page = serde_json::from_str::<ArtistsModel>(following_json.as_str())?.artists;
+        artists.extend(page.items);
+        after = page.cursors.and_then(|cursors| cursors.after);
+        if after.is_none() {
+            return Ok(artists);
+        }
+    }
 }
 
+/// Saved podcasts by name. Any show librespot can't load is left out with a warning.
+async fn fetch_saved_shows(
+    api_client: &reqwest::Client,
+    token: &str,
+    session: &Session,
+) -> Result<HashMap<String, Show>, Box<dyn std::error::Error>> {
+    let shows_endpoint = format!("{}/me/shows?limit=50", api_base_url());
+    let shows_json = request(api_client, Method::GET, shows_endpoint, token, None).await?;
+    let fetched_shows = serde_json::from_str::<ShowsModel>(shows_json.as_str())?;
+    let mut shows = HashMap::new();
+    for show_wrapper in fetched_shows.items {
+        let show = match SpotifyId::from_base62(&show_wrapper.show.id) {
+            Ok(show_id) => Show::get(session, show_id).await.ok(),
+            Err(_) => None,
+        };
+        match show {
+            Some(show) => {
+                shows.insert(show.name.to_owned(), show);
+            }
+            None => print_warning(&format!("Couldn't load podcast {}", show_wrapper.show.name)),
+        }
+    }
+    Ok(shows)
+}
+
+/// A Web API access token, or an empty one (which the API rejects) if none could be had
+pub async fn fetch_token(session: &Session) -> String {
+    Auth::access_token(session).await.unwrap_or_else(|err| {
+        println(&err);
+        String::new()
+    })
+}
+
+fn api_base_url() -> &'static str {
+    &Config::settings().api_base_url
+}
+
+/// Calls the Web API, sending `body` as JSON if there is one, and returns the response text
 pub async fn request(
     api_client: &reqwest::Client,
+    method: Method,
     endpoint: String,
-    token: &Token,
+    token: &str,
+    body: Option<serde_json::Value>,
 ) -> Result<String, reqwest::Error> {
-    let data = api_client
-        .get(endpoint)
+    let mut builder = api_client
+        .request(method, endpoint)
         .header("Accept", "application/json")
-        .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", token.access_token))
-        .send()
-        .await?
-        .text()
-        .await?;
+        .header("Authorization", format!("Bearer {}", token));
+    builder = match body {
+        Some(body) => builder.json(&body),
+        None => builder.header("Content-Type", "application/json"),
+    };
+    let data = builder.send().await?.error_for_status()?.text().await?;
     Ok(data)
 }
 
@@ -98,3 +644,67 @@ pub async fn fetch_individual<T: Metadata>(
         Err(SpotifyIdError) => panic!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::OwnerModel;
+
+    #[test]
+    fn moves_down_insert_past_the_target() {
+        assert_eq!(insert_before(0, 2), 3);
+        assert_eq!(insert_before(3, 1), 1);
+        assert_eq!(insert_before(2, 2), 2);
+    }
+
+    #[test]
+    fn keeps_the_snapshot_from_our_own_changes() {
+        let mut library_playlist = library_playlist("a", "Mix", "me");
+        library_playlist.set_snapshot("{\"snapshot_id\":\"2\"}");
+        assert_eq!(library_playlist.snapshot_id, "2");
+        library_playlist.set_snapshot("");
+        assert_eq!(library_playlist.snapshot_id, "2");
+    }
+
+    fn library_playlist(id: &str, name: &str, owner: &str) -> LibraryPlaylist {
+        let model = PlaylistModel {
+            id: id.to_string(),
+            name: name.to_string(),
+            owner: OwnerModel {
+                id: owner.to_string(),
+                display_name: None,
+            },
+            collaborative: false,
+            snapshot_id: String::from("1"),
+        };
+        let playlist = Playlist {
+            revision: Vec::new(),
+            user: owner.to_string(),
+            name: name.to_string(),
+            tracks: Vec::new(),
+        };
+        LibraryPlaylist::new(model, playlist, "me", 0)
+    }
+
+    #[test]
+    fn labels_playlists_sharing_a_name_apart() {
+        let playlists = [
+            library_playlist("b", "Chill", "me"),
+            library_playlist("a", "Chill", "me"),
+            library_playlist("c", "Chill", "alice"),
+        ];
+        let fetcher = Fetcher {
+            playlists: playlists
+                .into_iter()
+                .map(|playlist| (playlist.id.to_owned(), playlist))
+                .collect(),
+            albums: HashMap::new(),
+            artists: HashMap::new(),
+            shows: HashMap::new(),
+        };
+        let labelled = fetcher.playlists_by_label();
+        assert_eq!(labelled["Chill"].id, "a");
+        assert_eq!(labelled["Chill (2)"].id, "b");
+        assert_eq!(labelled["Chill (by alice)"].id, "c");
+    }
+}
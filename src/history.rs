@@ -0,0 +1,109 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use chrono::{Local, NaiveDate, TimeZone};
+
+use crate::config as Config;
+use crate::model::HistoryEntryModel;
+
+pub const HISTORY_FILE: &str = "history.jsonl";
+
+fn history_path() -> PathBuf {
+    let mut path = Config::data_path();
+    path.push(HISTORY_FILE);
+    path
+}
+
+/// Appends one played track to the history file
+pub fn record(entry: &HistoryEntryModel) -> io::Result<()> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+}
+
+/// Every recorded entry, oldest first. Lines that can't be parsed are skipped.
+pub fn load() -> Vec<HistoryEntryModel> {
+    let contents = fs::read_to_string(history_path()).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Entries played at or after `since` (unix seconds), oldest first
+pub fn load_since(since: Option<i64>) -> Vec<HistoryEntryModel> {
+    let mut entries = load();
+    if let Some(since) = since {
+        entries.retain(|entry| entry.played_at >= since);
+    }
+    entries
+}
+
+pub fn now() -> i64 {
+    Local::now().timestamp()
+}
+
+/// Parses `--since` values: a relative age like "30m", "12h", "3d", "2w" or a date
+/// like "2024-05-01", returning unix seconds
+pub fn parse_since(value: &str, now: i64) -> Option<i64> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let midnight = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        return Some(midnight.timestamp());
+    }
+    let split = value.len().checked_sub(1)?;
+    let amount: i64 = value.get(..split)?.parse().ok()?;
+    let unit_seconds: i64 = match value.get(split..)? {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    // Checked, and kept to times chrono can represent, since huge amounts would overflow
+    let since = now.checked_sub(amount.checked_mul(unit_seconds)?)?;
+    Local
+        .timestamp_opt(since, 0)
+        .single()
+        .map(|time| time.timestamp())
+}
+
+pub fn format_played_at(played_at: i64) -> String {
+    match Local.timestamp_opt(played_at, 0).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        None => String::from("????-??-?? ??:??"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relative_ages() {
+        let now = 1_000_000;
+        assert_eq!(parse_since("30m", now), Some(now - 30 * 60));
+        assert_eq!(parse_since("2d", now), Some(now - 2 * 24 * 60 * 60));
+        assert_eq!(parse_since("1w", now), Some(now - 7 * 24 * 60 * 60));
+    }
+
+    #[test]
+    fn rejects_unknown_units() {
+        assert_eq!(parse_since("3y", 0), None);
+        assert_eq!(parse_since("d", 0), None);
+        assert_eq!(parse_since("", 0), None);
+        assert_eq!(parse_since("9999999999999999m", 0), None);
+        assert_eq!(parse_since("999999999999999999w", 0), None);
+    }
+
+    #[test]
+    fn parses_dates_as_local_midnight() {
+        let since = parse_since("2024-05-01", 0).unwrap();
+        assert_eq!(format_played_at(since), "2024-05-01 00:00");
+    }
+}
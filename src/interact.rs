@@ -1,18 +1,20 @@
 use std::{io::{self, Write}, time::Duration};
-use console::style;
+use console::{Style, Term};
 use dialoguer::{Password, FuzzySelect, theme::ColorfulTheme};
 use indicatif::{ProgressBar, ProgressStyle};
 use text_io::read;
 
+use crate::config as Config;
+
 pub fn get() -> String {
-    print!("{} ", style(">>").green());
+    print!("{} ", accent().apply_to(">>"));
     io::stdout().flush().unwrap();
     let input: String = read!("{}\n");
     input.trim().to_string()
 }
 
 pub fn get_username() -> String {
-    print!("{} ", "Enter Username:");
+    print!("Enter Username: ");
     io::stdout().flush().unwrap();
     let input: String = read!("{}\n");
     input.trim().to_string()
@@ -25,18 +27,36 @@ pub fn get_password() -> String {
 }
 
 pub fn println(text: &str) {
-    println!("{}", style(text).green())
+    println!("{}", accent().apply_to(text))
+}
+
+pub fn print_warning(text: &str) {
+    println!("{}", warning().apply_to(text))
+}
+
+fn accent() -> Style {
+    Style::from_dotted_str(&Config::settings().theme_accent)
+}
+
+fn warning() -> Style {
+    Style::from_dotted_str(&Config::settings().theme_warning)
+}
+
+// Spinner template in the accent colour
+fn accent_template() -> String {
+    let colour = &Config::settings().theme_accent;
+    format!("{{spinner:.{0}}} {{msg:.{0}}}", colour)
 }
 
 pub fn print_prompt() {
-    print!("{} ", style(">>").green());
+    print!("{} ", accent().apply_to(">>"));
     io::stdout().flush().unwrap();
 }
 
 pub fn start_session_spinner() -> ProgressBar {
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(
-        ProgressStyle::with_template("{spinner:.green} {msg:.green}").unwrap()
+        ProgressStyle::with_template(&accent_template()).unwrap()
     );
     spinner.set_message("Starting session...");
     spinner.enable_steady_tick(Duration::from_millis(120));
@@ -50,7 +70,7 @@ pub fn stop_session_spinner(spinner: ProgressBar) {
 pub fn start_player_spinner() -> ProgressBar {
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(
-        ProgressStyle::with_template("{spinner:.blue} {msg:.blue}").unwrap()
+        ProgressStyle::with_template(&accent_template()).unwrap()
     );
     spinner.enable_steady_tick(Duration::from_millis(120));
     spinner
@@ -62,6 +82,41 @@ pub fn stop_player_spinner(spinner: &ProgressBar) {
     print_prompt();
 }
 
+/// Prints above the player spinner, which redraws itself on the next tick
+pub fn print_during_playback(lines: &[String]) {
+    let _ = Term::stderr().clear_line();
+    for line in lines {
+        println(line);
+    }
+}
+
+pub fn print_skipped(spinner: &ProgressBar, track_name: &str) {
+    spinner.println(warning().apply_to(format!("Skipped unavailable track: {}", track_name)).to_string());
+}
+
+pub fn start_loading_spinner(message: &'static str) -> ProgressBar {
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_style(
+        ProgressStyle::with_template(&accent_template()).unwrap()
+    );
+    spinner.set_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(120));
+    spinner
+}
+
+pub fn format_duration(duration_ms: i32) -> String {
+    let seconds = duration_ms.max(0) / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub fn select_index(items: &[String]) -> Option<usize> {
+    FuzzySelect::with_theme(&ColorfulTheme::default())
+        .items(items)
+        .default(0)
+        .interact_opt()
+        .unwrap()
+}
+
 pub fn select_item(items: Vec<&String>) -> String {
     let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
         .items(&items)
@@ -74,11 +129,58 @@ pub fn select_item(items: Vec<&String>) -> String {
     }
 }
 
+/// Like `select_item`, for names that matched `query` equally well, best match first
+pub fn select_match(items: Vec<&String>, query: &str) -> String {
+    let selection = FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Which \"{}\"?", query))
+        .items(&items)
+        .default(0)
+        .interact_opt()
+        .unwrap();
+    match selection {
+        Some(index) => items[index].to_owned(),
+        None => String::new(),
+    }
+}
+
 pub fn print_help() {
     println("Available Commands:");
     println("play playlist/album         Select and play a playlist/album");
     println("play playlist/album <name>  Play a playlist/album with name <name>");
-    println("ls playlist/album           Print list of available playlists/albums");
+    println("play playlist/album <name> --from <n>  Start from track number <n>");
+    println("play playlist/album <name> --pick      Pick the first track to play");
+    println("play playlist/album shuffle <name>     Play in random order");
+    println("play playlist/album <name> --spread    Shuffle without the same artist twice in a row");
+    println("play playlist/album <name> --no-shuffle  Play in order when shuffle is on by default");
+    println("play artist <name> [--all]  Play an artist's top tracks (or whole discography)");
+    println("play artist shuffle <name> --all  Play an artist's discography shuffled");
+    println("play podcast <name>         Play a saved podcast, resuming where you left off");
+    println("show playlist/album <name>  List the tracks of a playlist/album");
+    println("show podcast <name>         List the episodes of a saved podcast");
+    println("artist <name>               List an artist's top tracks, releases and related artists");
+    println("ls playlist/album/artist    Print list of playlists/albums/followed artists");
+    println("ls podcasts                 Print list of saved podcasts");
+    println("  --sort name|added|tracks|artist  Order of the list (default name)");
+    println("  --artist <x> / --owner me|<x>    Only albums by x / playlists owned by you or x");
+    println("  --limit <n> / --group            Print at most n / under a heading per artist or owner");
+    println("now [--json]                Print what is playing (press 'n' while playing)");
+    println("history [n] [--since <t>]   Print recently played tracks (t: 30m, 12h, 3d, 2024-05-01)");
+    println("play history [--since <t>]  Replay recently played tracks");
+    println("resume                      Continue where playback last stopped");
+    println("playlist create <name>      Create an empty playlist");
+    println("playlist add <name> <track> Add a track (current, a URI or link)");
+    println("playlist rm <name> <track>  Remove a track by number, or the first copy of current, a URI or link");
+    println("playlist mv <name> <n> <m>  Move track n to position m");
+    println("shuffle on|off              Shuffle or unshuffle what's left (press 's' while playing)");
+    println("like / unlike               Add or remove the current track from liked songs (press 'l' while playing)");
+    println("save album                  Save the album of the current track");
+    println("unsave album [name]         Remove a saved album");
+    println("repeat off/one/all          Set repeat mode (press 'r' while playing to cycle)");
+    println("config get [key]            Print settings and where each comes from");
+    println("config set <key> <value>    Save a setting to the config file");
+    println("switch [profile]            Log in with another saved profile");
+    println("login [profile]             Log in again, or to another profile");
+    println("logout [profile]            Forget a profile's login, history and saved session");
     println("whoami                      Print your username");
     println("help                        Print list of available commands");
     println("quit                        Exit program");
@@ -1,33 +1,61 @@
 use console::{Key, Term};
-use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
 use librespot::core::session::Session;
-use librespot::core::spotify_id::SpotifyId;
-use librespot::metadata::{Album, Artist, Metadata, Playlist, Track};
+use librespot::core::spotify_id::{SpotifyAudioType, SpotifyId};
+use librespot::metadata::{Metadata, Show, Track};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
-use std::collections::HashMap;
-use std::sync::mpsc::Sender;
-use std::{process, thread};
+use std::collections::{HashMap, HashSet};
+use std::{env, process};
+use tokio::sync::{mpsc::UnboundedSender, oneshot};
 
 use crate::command::Command;
 use crate::command::CommandType;
-use crate::fetch::Fetcher;
+use crate::config as Config;
+use crate::fetch::{
+    fetch_artist, fetch_resume_position, search_artist, ArtistCatalog, Fetcher, LibraryAlbum,
+    LibraryPlaylist,
+};
+use crate::history as History;
 use crate::interact::println;
 use crate::interact::{self as Interact, print_help};
-use crate::play::{Message, TrackData};
+use crate::listing::{self as Listing, ListOptions, ListingEntry, SortKey};
+use crate::matching as Matching;
+use crate::model::{ArtistAlbumModel, StatusModel};
+use crate::play::{
+    fetch_track_data, format_artists, track_name, Message, PlayerStatus, Repeat, TrackData,
+};
+use crate::profile as Profile;
+use crate::progress as Progress;
+use crate::resume as Resume;
+use crate::shuffle::{self as Shuffle, ShuffleMode};
+
+// Entries printed by `history` when no count is given
+const HISTORY_LENGTH: usize = 20;
+// Track metadata requests kept in flight at once when listing a collection
+const FETCH_CONCURRENCY: usize = 16;
 
 pub struct Invoker {
     session: Session,
     fetcher: Fetcher,
-    transmitter: Sender<Message>,
+    transmitter: UnboundedSender<Message>,
+    repeat: Repeat,
+    // Set by `switch` and `login` for the main loop to pick up
+    profile_switch: Option<String>,
 }
 
 impl Invoker {
-    pub fn new(session: Session, fetcher: Fetcher, transmitter: Sender<Message>) -> Invoker {
+    pub fn new(
+        session: Session,
+        fetcher: Fetcher,
+        transmitter: UnboundedSender<Message>,
+    ) -> Invoker {
         Invoker {
             session,
             fetcher,
             transmitter,
+            repeat: Config::settings().repeat,
+            profile_switch: None,
         }
     }
 
@@ -35,11 +63,27 @@ impl Invoker {
         match command.command_type {
             CommandType::Play => self.play(command.args).await,
             CommandType::Pause => (),
+            CommandType::Show => self.show(command.args).await,
+            CommandType::Now => self.now(command.args).await,
+            CommandType::Artist => self.artist(command.args).await,
+            CommandType::History => self.history(command.args),
+            CommandType::Resume => self.resume().await,
+            CommandType::Playlist => self.edit_playlist(command.args).await,
+            CommandType::Shuffle => self.shuffle(command.args).await,
+            CommandType::Like => println(&self.like(true).await),
+            CommandType::Unlike => println(&self.like(false).await),
+            CommandType::Save => self.save_album(command.args).await,
+            CommandType::Unsave => self.unsave_album(command.args).await,
             CommandType::Stop => self.stop().await,
+            CommandType::Repeat => self.set_repeat(command.args),
             CommandType::List => self.list(command.args),
+            CommandType::Config => self.config(command.args),
+            CommandType::Switch => self.switch(command.args),
+            CommandType::Login => self.login(command.args).await,
+            CommandType::Logout => self.logout(command.args).await,
             CommandType::Whoami => self.whoami(),
             CommandType::Help => self.help(),
-            CommandType::Quit => self.quit(),
+            CommandType::Quit => self.quit().await,
             _ => self.unknown(),
         }
 
@@ -52,56 +96,168 @@ impl Invoker {
             return;
         }
         let first_arg = args.remove(0);
-        let mut shuffle = false;
-        match args.get(0) {
-            None => (),
-            Some(arg) => {
-                if arg == "shuffle" {
-                    shuffle = true;
-                    args.remove(0);
-                }
+        // Podcasts play in order unless asked otherwise, whatever the configured default
+        let mut shuffle = match Config::settings().shuffle && first_arg != "podcast" {
+            true => Some(ShuffleMode::Random),
+            false => None,
+        };
+        if let Some(arg) = args.first() {
+            if arg == "shuffle" {
+                shuffle = Some(ShuffleMode::Random);
+                args.remove(0);
             }
         }
+        if take_flag(&mut args, "--spread") {
+            shuffle = Some(ShuffleMode::ArtistSpread);
+        }
+        if take_flag(&mut args, "--no-shuffle") {
+            shuffle = None;
+        }
+        let start_track = match take_start_track(&mut args) {
+            Ok(start_track) => start_track,
+            Err(_) => {
+                self.unknown();
+                return;
+            }
+        };
+        let whole_discography = take_flag(&mut args, "--all");
+        let since = match take_since(&mut args) {
+            Ok(since) => since,
+            Err(_) => {
+                self.unknown();
+                return;
+            }
+        };
         let joined_args = args.join(" ");
-        match first_arg.as_str() {
-            // TODO: Merge these somehow (or maybe not...)
+        let track_collection = match first_arg.as_str() {
             "playlist" => {
-                let track_collection =
-                    select_track_collection(self.fetcher.playlists(), joined_args);
-                match track_collection {
-                    None => {
-                        println("Not found");
-                        return;
-                    }
-                    Some(tc) => {
-                        play_track_collection(tc, shuffle, &self.session, &self.transmitter).await;
-                    }
-                }
+                let playlists = self.fetcher.playlists_by_label();
+                select_track_collection(&playlists, joined_args).map(TrackList::of)
             }
             "album" => {
-                let track_collection = select_track_collection(self.fetcher.albums(), joined_args);
-                match track_collection {
-                    None => {
-                        println("Not found");
-                        return;
-                    }
-                    Some(tc) => {
-                        play_track_collection(tc, false, &self.session, &self.transmitter).await;
-                    }
+                select_track_collection(self.fetcher.albums(), joined_args).map(TrackList::of)
+            }
+            "podcast" => {
+                select_track_collection(self.fetcher.shows(), joined_args).map(TrackList::of)
+            }
+            "history" => {
+                let track_collection = history_collection(since);
+                if track_collection.tracks.is_empty() {
+                    println("Nothing played yet");
+                    return;
                 }
+                Some(track_collection)
+            }
+            "artist" => {
+                self.artist_collection(&joined_args, whole_discography)
+                    .await
             }
             _ => {
                 self.unknown();
                 return;
             }
         };
+        let started = match track_collection {
+            None => {
+                println("Not found");
+                return;
+            }
+            Some(tc) => {
+                play_track_collection(&tc, shuffle, start_track, &self.session, &self.transmitter)
+                    .await
+            }
+        };
+        if started {
+            self.wait_during_playback().await;
+        }
+    }
+
+    /// Picks up the queue and position saved when playback last stopped
+    pub async fn resume(&mut self) {
+        let saved = match Resume::load() {
+            Some(saved) => saved,
+            None => {
+                println("Nothing to resume");
+                return;
+            }
+        };
+        let tracks: Result<Vec<SpotifyId>, _> = saved
+            .tracks
+            .iter()
+            .map(|uri| SpotifyId::from_uri(uri))
+            .collect();
+        let tracks = match tracks {
+            Ok(tracks) if saved.current < tracks.len() => tracks,
+            _ => {
+                println("The saved session can't be read");
+                return;
+            }
+        };
+        if let Ok(repeat) = saved.repeat.parse::<Repeat>() {
+            self.repeat = repeat;
+            self.transmitter.send(Message::SetRepeat(repeat)).unwrap();
+        }
+        let unshuffled = match saved.shuffled {
+            true => Some(
+                saved
+                    .unshuffled
+                    .iter()
+                    .filter_map(|uri| SpotifyId::from_uri(uri).ok())
+                    .collect(),
+            ),
+            false => None,
+        };
+        let message = Message::StartPlaying {
+            tracks,
+            current: saved.current,
+            unshuffled,
+            position_ms: saved.position_ms,
+            source: saved.source,
+        };
+        self.transmitter.send(message).unwrap();
+        self.wait_during_playback().await;
+    }
+
+    /// Mentions the saved session, if there is one, when the prompt first opens
+    pub fn offer_resume(&self) {
+        if let Some(saved) = Resume::load() {
+            let position = Interact::format_duration(saved.position_ms as i32);
+            let name = match saved.name.is_empty() {
+                true => String::from("where you left off"),
+                false => saved.name,
+            };
+            println(&format!(
+                "Type 'resume' to continue {} ({}) from {}",
+                name, position, saved.source
+            ));
+        }
+    }
 
-        // Wait for user input to stop playing music
+    /// Handles playback hotkeys until a key that stops playback is pressed
+    async fn wait_during_playback(&mut self) {
         let stdout = Term::stdout();
         loop {
             let key_result = stdout.read_key();
             match key_result {
                 Ok(Key::Unknown | Key::UnknownEscSeq(_)) => (),
+                Ok(Key::Char('r')) => {
+                    self.cycle_repeat();
+                }
+                Ok(Key::Char('n')) => {
+                    if let Some(status) = self.status().await {
+                        Interact::print_during_playback(&status_lines(&status));
+                    }
+                }
+                Ok(Key::Char('s')) => {
+                    if let Some(status) = self.status().await {
+                        let message = self.set_shuffle(!status.shuffled);
+                        Interact::print_during_playback(&[message]);
+                    }
+                }
+                Ok(Key::Char('l')) => {
+                    let message = self.like(true).await;
+                    Interact::print_during_playback(&[message]);
+                }
                 _ => {
                     self.stop().await;
                     break;
@@ -110,43 +266,632 @@ impl Invoker {
         }
     }
 
+    pub async fn show(&self, mut args: Vec<String>) {
+        if args.is_empty() {
+            self.unknown();
+            return;
+        }
+        let first_arg = args.remove(0);
+        let joined_args = args.join(" ");
+        let playlists = self.fetcher.playlists_by_label();
+        let tracks = match first_arg.as_str() {
+            "playlist" => select_track_collection(&playlists, joined_args).map(|tc| tc.tracks()),
+            "album" => {
+                select_track_collection(self.fetcher.albums(), joined_args).map(|tc| tc.tracks())
+            }
+            "podcast" => {
+                select_track_collection(self.fetcher.shows(), joined_args).map(|tc| tc.tracks())
+            }
+            _ => {
+                self.unknown();
+                return;
+            }
+        };
+        match tracks {
+            None => println("Not found"),
+            Some(tracks) => {
+                for line in fetch_track_listing(&tracks, &self.session).await {
+                    println(&line);
+                }
+            }
+        }
+    }
+
+    pub async fn now(&self, args: Vec<String>) {
+        let status = match self.status().await {
+            Some(status) => status,
+            None => {
+                println("Player is not running");
+                return;
+            }
+        };
+        match args.iter().any(|arg| arg == "--json") {
+            true => {
+                let json = serde_json::to_string_pretty(&status_model(&status)).unwrap();
+                println!("{}", json);
+            }
+            false => {
+                for line in status_lines(&status) {
+                    println(&line);
+                }
+            }
+        }
+    }
+
+    pub async fn artist(&self, args: Vec<String>) {
+        let catalog = match self.lookup_artist(&args.join(" ")).await {
+            Some(catalog) => catalog,
+            None => {
+                println("Not found");
+                return;
+            }
+        };
+        println(&catalog.name);
+        println("Top tracks:");
+        for line in fetch_track_listing(&catalog.top_tracks, &self.session).await {
+            println(&line);
+        }
+        print_releases("Albums:", &catalog.albums);
+        print_releases("Singles:", &catalog.singles);
+        if !catalog.related.is_empty() {
+            println("Related artists:");
+            for related in &catalog.related {
+                println(&format!("  {}", related.name));
+            }
+        }
+    }
+
+    /// A followed artist if one clearly matches, otherwise the best match in the whole
+    /// catalog. With no name, offers a picker of followed artists.
+    async fn lookup_artist(&self, name: &str) -> Option<ArtistCatalog> {
+        let followed_artists = self.fetcher.artists();
+        let followed_names = followed_artists.keys().collect();
+        // Anything less than a clear winner is more likely an artist the user doesn't follow
+        let followed_name = match name.is_empty() {
+            true => select_name(followed_names, name),
+            false => Matching::clear_winner(&Matching::rank(followed_names, name)),
+        };
+        let spinner = Interact::start_loading_spinner("Looking up artist...");
+        let artist_model = match followed_name {
+            Some(followed_name) => followed_artists.get(followed_name).cloned(),
+            None if name.is_empty() => None,
+            None => search_artist(&self.session, name).await.ok().flatten(),
+        };
+        let catalog = match artist_model {
+            Some(artist_model) => fetch_artist(&self.session, &artist_model).await.ok(),
+            None => None,
+        };
+        spinner.finish_and_clear();
+        catalog
+    }
+
+    /// The artist's top tracks, or every track they have released
+    async fn artist_collection(&self, name: &str, whole_discography: bool) -> Option<TrackList> {
+        let catalog = self.lookup_artist(name).await?;
+        let name = catalog.name.to_owned();
+        let tracks = match whole_discography {
+            false => catalog.top_tracks,
+            true => {
+                let spinner = Interact::start_loading_spinner("Loading discography...");
+                let tracks = catalog.discography(&self.session).await;
+                spinner.finish_and_clear();
+                tracks
+            }
+        };
+        Some(TrackList { name, tracks })
+    }
+
+    pub fn history(&self, mut args: Vec<String>) {
+        let since = match take_since(&mut args) {
+            Ok(since) => since,
+            Err(_) => {
+                self.unknown();
+                return;
+            }
+        };
+        let count = match args.first() {
+            None => HISTORY_LENGTH,
+            Some(arg) => match arg.parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => {
+                    self.unknown();
+                    return;
+                }
+            },
+        };
+        let entries = History::load_since(since);
+        if entries.is_empty() {
+            println("Nothing played yet");
+            return;
+        }
+        for entry in entries.iter().skip(entries.len().saturating_sub(count)) {
+            let name = match entry.name.is_empty() {
+                true => &entry.uri,
+                false => &entry.name,
+            };
+            println(&format!(
+                "{}  {}  ({})",
+                History::format_played_at(entry.played_at),
+                name,
+                entry.source
+            ));
+        }
+    }
+
+    /// `playlist create|add|rm|mv`, changing the playlist on Spotify and in the library
+    pub async fn edit_playlist(&mut self, mut args: Vec<String>) {
+        if args.is_empty() {
+            self.unknown();
+            return;
+        }
+        let action = args.remove(0);
+        let result = match action.as_str() {
+            "create" if !args.is_empty() => {
+                let name = args.join(" ");
+                self.fetcher
+                    .create_playlist(&self.session, &name)
+                    .await
+                    .map(|_| format!("Created {}", name))
+            }
+            "add" | "rm" if args.len() >= 2 => {
+                let track = args.pop().unwrap_or_default();
+                let (name, playlist_id) = match self.select_playlist(&args.join(" ")) {
+                    Some(selection) => selection,
+                    None => {
+                        println("Not found");
+                        return;
+                    }
+                };
+                match action.as_str() {
+                    "add" => match self.resolve_track_arg(&track).await {
+                        Some(track_id) => self
+                            .fetcher
+                            .add_to_playlist(&self.session, &playlist_id, track_id)
+                            .await
+                            .map(|_| format!("Added to {}", name)),
+                        None => {
+                            println("Couldn't tell which track that is");
+                            return;
+                        }
+                    },
+                    _ => match self.resolve_playlist_position(&playlist_id, &track).await {
+                        Some(position) => self
+                            .fetcher
+                            .remove_from_playlist(&self.session, &playlist_id, position)
+                            .await
+                            .map(|_| format!("Removed track {} from {}", position + 1, name)),
+                        None => {
+                            println("Couldn't find that track in the playlist");
+                            return;
+                        }
+                    },
+                }
+            }
+            "mv" if args.len() >= 3 => {
+                let to = args.pop().and_then(|arg| arg.parse::<usize>().ok());
+                let from = args.pop().and_then(|arg| arg.parse::<usize>().ok());
+                let (from, to) = match (from, to) {
+                    (Some(from), Some(to)) if from > 0 && to > 0 => (from - 1, to - 1),
+                    _ => {
+                        self.unknown();
+                        return;
+                    }
+                };
+                let (name, playlist_id) = match self.select_playlist(&args.join(" ")) {
+                    Some(selection) => selection,
+                    None => {
+                        println("Not found");
+                        return;
+                    }
+                };
+                self.fetcher
+                    .move_in_playlist(&self.session, &playlist_id, from, to)
+                    .await
+                    .map(|_| format!("Moved track {} to {} in {}", from + 1, to + 1, name))
+            }
+            _ => {
+                self.unknown();
+                return;
+            }
+        };
+        match result {
+            Ok(message) => println(&message),
+            Err(err) => println(&format!("Couldn't change the playlist: {}", err)),
+        }
+    }
+
+    /// Label and id of the playlist matching `query`
+    fn select_playlist(&self, query: &str) -> Option<(String, String)> {
+        let playlists = self.fetcher.playlists_by_label();
+        let label = select_name(playlists.keys().collect(), query)?;
+        Some((label.to_owned(), playlists[label].id.to_owned()))
+    }
+
+    /// Index in the playlist with id `playlist_id` of a track number, or of the first copy
+    /// of `current` or a Spotify URI or link
+    async fn resolve_playlist_position(&self, playlist_id: &str, track: &str) -> Option<usize> {
+        if let Ok(number) = track.parse::<usize>() {
+            return number.checked_sub(1);
+        }
+        let track_id = self.resolve_track_arg(track).await?;
+        let library_playlist = self.fetcher.playlists().get(playlist_id)?;
+        let tracks = &library_playlist.playlist.tracks;
+        tracks.iter().position(|id| *id == track_id)
+    }
+
+    /// Reads `current` or a Spotify URI or link
+    async fn resolve_track_arg(&self, track: &str) -> Option<SpotifyId> {
+        if track == "current" {
+            return self
+                .status()
+                .await?
+                .current
+                .map(|track_data| track_data.track.id);
+        }
+        if let Some(link) = track.strip_prefix("https://open.spotify.com/track/") {
+            let base62 = link.split('?').next()?;
+            return SpotifyId::from_base62(base62).ok();
+        }
+        SpotifyId::from_uri(track).ok()
+    }
+
+    /// Adds the current track to liked songs, or takes it out, and says how it went
+    pub async fn like(&self, like: bool) -> String {
+        let track_data = match self.status().await.and_then(|status| status.current) {
+            Some(track_data) => track_data,
+            None => return String::from("Nothing playing"),
+        };
+        let result = match like {
+            true => {
+                self.fetcher
+                    .like_track(&self.session, track_data.track.id)
+                    .await
+            }
+            false => {
+                self.fetcher
+                    .unlike_track(&self.session, track_data.track.id)
+                    .await
+            }
+        };
+        match (result, like) {
+            (Ok(_), true) => format!("Liked {}", track_name(&track_data)),
+            (Ok(_), false) => format!("Unliked {}", track_name(&track_data)),
+            (Err(err), _) => format!("Couldn't change liked songs: {}", err),
+        }
+    }
+
+    /// `save album` adds the album of the current track to the library
+    pub async fn save_album(&mut self, args: Vec<String>) {
+        if args.first().map(String::as_str) != Some("album") {
+            self.unknown();
+            return;
+        }
+        let album_id = match self.status().await.and_then(|status| status.current) {
+            Some(track_data) => track_data.track.album,
+            None => {
+                println("Nothing playing");
+                return;
+            }
+        };
+        match self.fetcher.save_album(&self.session, album_id).await {
+            Ok(name) => println(&format!("Saved {}", name)),
+            Err(err) => println(&format!("Couldn't save the album: {}", err)),
+        }
+    }
+
+    /// `unsave album [name]` removes a saved album from the library
+    pub async fn unsave_album(&mut self, mut args: Vec<String>) {
+        if args.is_empty() || args.remove(0) != "album" {
+            self.unknown();
+            return;
+        }
+        let names: Vec<&String> = self.fetcher.albums().keys().collect();
+        let name = match select_name(names, &args.join(" ")) {
+            Some(name) => name.to_owned(),
+            None => {
+                println("Not found");
+                return;
+            }
+        };
+        match self.fetcher.unsave_album(&self.session, &name).await {
+            Ok(_) => println(&format!("Removed {}", name)),
+            Err(err) => println(&format!("Couldn't remove the album: {}", err)),
+        }
+    }
+
+    /// `shuffle on|off` while something is playing
+    pub async fn shuffle(&self, args: Vec<String>) {
+        let shuffled = match args.first().map(String::as_str) {
+            Some("on") => true,
+            Some("off") => false,
+            _ => {
+                self.unknown();
+                return;
+            }
+        };
+        match self.status().await.and_then(|status| status.current) {
+            Some(_) => println(&self.set_shuffle(shuffled)),
+            None => println("Nothing playing"),
+        }
+    }
+
+    fn set_shuffle(&self, shuffled: bool) -> String {
+        self.transmitter
+            .send(Message::SetShuffle(shuffled))
+            .unwrap();
+        format!("Shuffle {}", on_off(shuffled))
+    }
+
     pub async fn stop(&mut self) {
         let message = Message::StopPlaying;
         self.transmitter.send(message).unwrap();
     }
 
+    /// Replaces whatever is playing with `tracks`, starting at index `current`, without
+    /// waiting for user input
+    pub fn start_playback(&self, tracks: Vec<SpotifyId>, current: usize, source: String) {
+        let message = Message::StartPlaying {
+            tracks,
+            current,
+            unshuffled: None,
+            position_ms: 0,
+            source,
+        };
+        self.transmitter.send(message).unwrap();
+    }
+
+    pub fn cycle_repeat(&mut self) -> Repeat {
+        self.repeat = self.repeat.next();
+        self.transmitter
+            .send(Message::SetRepeat(self.repeat))
+            .unwrap();
+        self.repeat
+    }
+
+    /// Asks the player thread what it is doing, `None` if it has gone away
+    pub async fn status(&self) -> Option<PlayerStatus> {
+        let (reply, response) = oneshot::channel();
+        self.transmitter.send(Message::Status(reply)).ok()?;
+        response.await.ok()
+    }
+
+    pub fn session(&self) -> &Session {
+        &self.session
+    }
+
+    pub fn fetcher(&self) -> &Fetcher {
+        &self.fetcher
+    }
+
+    pub fn set_repeat(&mut self, args: Vec<String>) {
+        let repeat = match args.first() {
+            Some(arg) => arg.parse::<Repeat>(),
+            None => Ok(self.repeat.next()),
+        };
+        match repeat {
+            Ok(r) => {
+                self.repeat = r;
+                self.transmitter.send(Message::SetRepeat(r)).unwrap();
+                println(&format!("Repeat {}", r));
+            }
+            Err(_) => self.unknown(),
+        }
+    }
+
     pub fn list(&self, mut args: Vec<String>) {
         if args.is_empty() {
             self.unknown();
             return;
         }
         let first_arg = args.remove(0);
-        match first_arg.as_str() {
-            "playlist" | "playlists" => {
-                for p in self.fetcher.playlists().keys() {
-                    println(p);
+        let options = match take_list_options(&mut args) {
+            Ok(options) => options,
+            Err(err) => {
+                println(&err);
+                return;
+            }
+        };
+        let entries: Vec<ListingEntry> = match first_arg.as_str() {
+            "playlist" | "playlists" => self
+                .fetcher
+                .playlists_by_label()
+                .into_iter()
+                .map(|(label, playlist)| ListingEntry {
+                    label: match playlist.collaborative {
+                        true => format!("{} [collaborative]", label),
+                        false => label,
+                    },
+                    artist: playlist.owner.to_owned(),
+                    owned: playlist.owned,
+                    added: playlist.added,
+                    track_count: playlist.playlist.tracks.len(),
+                })
+                .collect(),
+            "album" | "albums" => self
+                .fetcher
+                .albums()
+                .iter()
+                .map(|(key, album)| ListingEntry {
+                    label: key.to_owned(),
+                    artist: format_artists(&album.artists, &[]),
+                    owned: false,
+                    added: album.added,
+                    track_count: album.album.tracks.len(),
+                })
+                .collect(),
+            "artist" | "artists" => self
+                .fetcher
+                .artists()
+                .keys()
+                .map(|name| ListingEntry {
+                    label: name.to_owned(),
+                    artist: name.to_owned(),
+                    owned: false,
+                    added: 0,
+                    track_count: 0,
+                })
+                .collect(),
+            "podcast" | "podcasts" | "shows" => self
+                .fetcher
+                .shows()
+                .iter()
+                .map(|(name, show)| ListingEntry {
+                    label: name.to_owned(),
+                    artist: show.publisher.to_owned(),
+                    owned: false,
+                    added: 0,
+                    track_count: show.episodes.len(),
+                })
+                .collect(),
+            _ => {
+                self.unknown();
+                return;
+            }
+        };
+        let mut heading: Option<String> = None;
+        for entry in Listing::arrange(entries, &options) {
+            if !options.group {
+                println(&entry.label);
+                continue;
+            }
+            if heading.as_ref() != Some(&entry.artist) {
+                println(&entry.artist);
+                heading = Some(entry.artist.to_owned());
+            }
+            println(&format!("  {}", entry.label));
+        }
+    }
+
+    pub fn config(&self, args: Vec<String>) {
+        match args.first().map(String::as_str) {
+            Some("get") if args.len() <= 2 => {
+                let cli_args: Vec<String> = env::args().collect();
+                let file = Config::load_file();
+                let env_var = |name: &str| env::var(name).ok();
+                let keys = match args.get(1) {
+                    Some(key) if Config::KEYS.contains(&key.as_str()) => vec![key.as_str()],
+                    Some(key) => {
+                        println(&format!("Unknown setting {}", key));
+                        return;
+                    }
+                    None => Config::KEYS.to_vec(),
+                };
+                for key in keys {
+                    let (value, source) = Config::resolve(key, &cli_args, &env_var, &file);
+                    println(&format!("{} = {}  ({})", key, value, source));
                 }
             }
-            "album" | "albums" => {
-                for a in self.fetcher.albums().keys() {
-                    println(a);
+            Some("set") if args.len() >= 3 => {
+                let value = args[2..].join(" ");
+                match Config::save_setting(&args[1], &value) {
+                    Ok(()) => println(&format!(
+                        "Saved to {}, takes effect next time you start",
+                        Config::config_path().display()
+                    )),
+                    Err(err) => println(&format!("Couldn't set {}: {}", args[1], err)),
                 }
             }
             _ => self.unknown(),
+        }
+    }
+
+    pub fn switch(&mut self, args: Vec<String>) {
+        let name = match args.first() {
+            Some(name) => name.to_owned(),
+            None => {
+                let profiles = Profile::list();
+                Interact::select_item(profiles.iter().collect())
+            }
         };
+        if name.is_empty() || !check_profile_name(&name) {
+            return;
+        }
+        if name == Profile::active() {
+            println(&format!("Already using {}", name));
+        } else if !Profile::exists(&name) {
+            println(&format!(
+                "No login saved for {}, use 'login {}'",
+                name, name
+            ));
+        } else {
+            self.profile_switch = Some(name);
+        }
+    }
+
+    pub async fn login(&mut self, args: Vec<String>) {
+        let active = Profile::active();
+        let name = args.first().unwrap_or(&active);
+        if !check_profile_name(name) {
+            return;
+        }
+        if *name == active {
+            self.stop_player().await;
+        }
+        // Forget any saved login so connecting asks for a username and password, whether or
+        // not the profile is the active one
+        if let Err(err) = Profile::forget_login(name) {
+            println(&format!("Couldn't forget the saved login: {}", err));
+        }
+        self.profile_switch = Some(name.to_owned());
+    }
+
+    pub async fn logout(&mut self, args: Vec<String>) {
+        let active = Profile::active();
+        let name = args.first().unwrap_or(&active);
+        if !check_profile_name(name) {
+            return;
+        }
+        if *name != active && !Profile::exists(name) {
+            println(&format!("No login saved for {}", name));
+            return;
+        }
+        if *name == active {
+            // Stop first, so the player doesn't save the session again after it's wiped
+            self.stop_player().await;
+        }
+        match Profile::forget(name) {
+            Ok(()) => println(&format!("Logged out of {}", name)),
+            Err(err) => println(&format!("Couldn't log out of {}: {}", name, err)),
+        }
+        if *name == active {
+            println("Log in again, or press Ctrl-C to quit");
+            self.profile_switch = Some(active);
+        }
+    }
+
+    /// The profile `switch` or `login` asked for, if any
+    pub fn take_profile_switch(&mut self) -> Option<String> {
+        self.profile_switch.take()
     }
 
     pub fn whoami(&self) {
-        println(&self.session.username());
+        let profile = Profile::active();
+        match profile == Profile::DEFAULT_PROFILE {
+            true => println(&self.session.username()),
+            false => println(&format!(
+                "{} (profile {})",
+                self.session.username(),
+                profile
+            )),
+        }
     }
 
     pub fn help(&self) {
         print_help();
     }
 
-    pub fn quit(&self) {
-        let message = Message::Quit;
-        self.transmitter.send(message).unwrap();
+    /// Saves the session for `resume`, then stops the player thread and disconnects
+    pub async fn stop_player(&self) {
+        let (reply, saved) = oneshot::channel();
+        if self.transmitter.send(Message::SaveSession(reply)).is_ok() {
+            let _ = saved.await;
+        }
+        let _ = self.transmitter.send(Message::Quit);
+        self.session.shutdown();
+    }
+
+    pub async fn quit(&self) {
+        self.stop_player().await;
         println("Come back soon!");
         process::exit(0);
     }
@@ -156,20 +901,317 @@ impl Invoker {
     }
 }
 
+fn status_lines(status: &PlayerStatus) -> Vec<String> {
+    let mut lines = Vec::new();
+    match &status.current {
+        None => lines.push(String::from("Nothing playing")),
+        Some(track_data) => {
+            let state = match status.playing {
+                true => "playing",
+                false => "paused",
+            };
+            lines.push(track_name(track_data));
+            if !track_data.album.is_empty() {
+                lines.push(format!("Album: {}", track_data.album));
+            }
+            lines.push(format!(
+                "{} / {} ({})",
+                Interact::format_duration(status.position_ms as i32),
+                Interact::format_duration(track_data.track.duration),
+                state
+            ));
+        }
+    }
+    lines.push(format!(
+        "Queue: {} tracks | shuffle {} | repeat {} | volume {}%",
+        status.queue_length,
+        on_off(status.shuffled),
+        status.repeat,
+        volume_percent(status.volume)
+    ));
+    lines
+}
+
+fn status_model(status: &PlayerStatus) -> StatusModel {
+    let current = status.current.as_ref();
+    StatusModel {
+        playing: status.playing,
+        track: current.map(|td| td.track.name.to_owned()),
+        artists: current
+            .map(|td| [td.artists.clone(), td.featured_artists.clone()].concat())
+            .unwrap_or_default(),
+        album: current.map(|td| td.album.to_owned()),
+        uri: current.and_then(|td| td.track.id.to_uri().ok()),
+        position_ms: status.position_ms,
+        duration_ms: current.map(|td| td.track.duration.max(0) as u32),
+        queue_length: status.queue_length,
+        shuffle: status.shuffled,
+        repeat: status.repeat.to_string(),
+        volume: volume_percent(status.volume),
+    }
+}
+
+fn on_off(enabled: bool) -> &'static str {
+    match enabled {
+        true => "on",
+        false => "off",
+    }
+}
+
+fn volume_percent(volume: u16) -> u8 {
+    (volume as u32 * 100 / u16::MAX as u32) as u8
+}
+
+fn print_releases(heading: &str, releases: &[ArtistAlbumModel]) {
+    println(heading);
+    for release in releases {
+        let year = release
+            .release_date
+            .get(..4)
+            .unwrap_or(&release.release_date);
+        println(&format!("  {}  {}", year, release.name));
+    }
+}
+
+/// Where in a collection playback should begin
+pub enum StartTrack {
+    First,
+    // 1-based, as printed by `show`
+    Number(usize),
+    Pick,
+}
+
+/// Removes `flag` from the arguments, returning whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes `flag` and the value following it from the arguments
+fn take_flag_value(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, String> {
+    match args.iter().position(|arg| arg == flag) {
+        None => Ok(None),
+        Some(index) => {
+            args.remove(index);
+            if index >= args.len() {
+                return Err(format!("{} needs a value", flag));
+            }
+            Ok(Some(args.remove(index)))
+        }
+    }
+}
+
+/// Pulls `--from <n>` or `--pick` out of the arguments to `play`
+fn take_start_track(args: &mut Vec<String>) -> Result<StartTrack, String> {
+    if take_flag(args, "--pick") {
+        return Ok(StartTrack::Pick);
+    }
+    match take_flag_value(args, "--from")? {
+        None => Ok(StartTrack::First),
+        Some(number) => number
+            .parse::<usize>()
+            .map(StartTrack::Number)
+            .map_err(|err| err.to_string()),
+    }
+}
+
+/// Pulls the sorting, filtering and grouping flags of `ls` out of the arguments
+fn take_list_options(args: &mut Vec<String>) -> Result<ListOptions, String> {
+    let group = take_flag(args, "--group");
+    let sort = match take_flag_value(args, "--sort")? {
+        Some(sort) => sort.parse::<SortKey>()?,
+        None => SortKey::Name,
+    };
+    let limit = match take_flag_value(args, "--limit")? {
+        Some(limit) => Some(limit.parse::<usize>().map_err(|err| err.to_string())?),
+        None => None,
+    };
+    Ok(ListOptions {
+        sort,
+        artist: take_flag_value(args, "--artist")?,
+        owner: take_flag_value(args, "--owner")?,
+        limit,
+        group,
+    })
+}
+
+/// Pulls `--since <age or date>` out of the arguments, as unix seconds
+fn take_since(args: &mut Vec<String>) -> Result<Option<i64>, String> {
+    match take_flag_value(args, "--since")? {
+        None => Ok(None),
+        Some(value) => match History::parse_since(&value, History::now()) {
+            Some(since) => Ok(Some(since)),
+            None => Err(format!("Can't read --since {}", value)),
+        },
+    }
+}
+
+/// Everything in the play history, most recently played first and without repeats
+fn history_collection(since: Option<i64>) -> TrackList {
+    let mut seen_uris = HashSet::new();
+    let tracks = History::load_since(since)
+        .into_iter()
+        .rev()
+        .filter(|entry| seen_uris.insert(entry.uri.to_owned()))
+        .filter_map(|entry| SpotifyId::from_uri(&entry.uri).ok())
+        .collect();
+    TrackList {
+        name: String::from("History"),
+        tracks,
+    }
+}
+
+/// Sends the collection to the player, returning false if nothing was started
 async fn play_track_collection(
     tc: &impl TrackCollection,
-    shuffle: bool,
+    shuffle: Option<ShuffleMode>,
+    start_track: StartTrack,
     session: &Session,
-    transmitter: &Sender<Message>,
-) {
-    let tracks = match shuffle {
-        false => tc.tracks(),
-        true => tc.shuffled_tracks(),
+    transmitter: &UnboundedSender<Message>,
+) -> bool {
+    let ordered_tracks = tc.tracks();
+    let start_index = match start_track {
+        StartTrack::First => None,
+        StartTrack::Number(number) => match number.checked_sub(1) {
+            Some(index) if index < ordered_tracks.len() => Some(index),
+            _ => {
+                println(&format!("No track {}", number));
+                return false;
+            }
+        },
+        StartTrack::Pick => {
+            let listing = fetch_track_listing(&ordered_tracks, session).await;
+            match Interact::select_index(&listing) {
+                Some(index) => Some(index),
+                None => return false,
+            }
+        }
+    };
+    let unshuffled = shuffle.map(|_| ordered_tracks.clone());
+    let (tracks, current) = match (start_index, shuffle) {
+        (None, None) => (ordered_tracks, 0),
+        (None, Some(mode)) => (shuffle_tracks(ordered_tracks, mode, session).await, 0),
+        // Earlier tracks stay in the queue as played, so repeat all comes back round to them
+        (Some(index), None) => (ordered_tracks, index),
+        (Some(index), Some(mode)) => {
+            // Chosen track first, then the rest of the collection shuffled
+            let first_track = ordered_tracks[index];
+            let mut tracks = shuffle_tracks(ordered_tracks, mode, session).await;
+            tracks.retain(|track_id| *track_id != first_track);
+            tracks.insert(0, first_track);
+            (tracks, 0)
+        }
+    };
+    let position_ms = match tracks.get(current) {
+        Some(track_id) if track_id.audio_type == SpotifyAudioType::Podcast => {
+            resume_position(session, *track_id).await
+        }
+        _ => 0,
+    };
+    let message = Message::StartPlaying {
+        tracks,
+        current,
+        unshuffled,
+        position_ms,
+        source: tc.name(),
     };
-    let session = session.clone();
-    let transmitter = transmitter.clone();
-    thread::spawn(move || block_on(send_to_player(tracks, session, transmitter)));
-    // This works?
+    transmitter.send(message).unwrap_or_else(|err| {
+        eprintln!("Problem sending tracks to player: {}", err);
+    });
+    true
+}
+
+/// Episodes pick up where the user left off here, or failing that on any other device,
+/// since this app doesn't report its progress to Spotify
+async fn resume_position(session: &Session, episode_id: SpotifyId) -> u32 {
+    let position_ms = match Progress::load(episode_id) {
+        Some(position_ms) => Ok(Some(position_ms)),
+        None => fetch_resume_position(session, episode_id).await,
+    };
+    match position_ms {
+        Ok(Some(position_ms)) => {
+            let position = Interact::format_duration(position_ms as i32);
+            println(&format!("Resuming at {}", position));
+            position_ms
+        }
+        _ => 0,
+    }
+}
+
+/// Whether `name` can be a profile, saying why not if it can't. Anything else, like "..",
+/// could point outside the profile's own directory.
+fn check_profile_name(name: &str) -> bool {
+    let valid = Profile::is_valid_name(name);
+    if !valid {
+        println("Profile names can only have letters, digits, - and _");
+    }
+    valid
+}
+
+/// `tracks` in a new order, either uniformly at random or with the same artist kept apart
+async fn shuffle_tracks(
+    mut tracks: Vec<SpotifyId>,
+    mode: ShuffleMode,
+    session: &Session,
+) -> Vec<SpotifyId> {
+    let mut rng = thread_rng();
+    match mode {
+        ShuffleMode::Random => {
+            tracks.shuffle(&mut rng);
+            tracks
+        }
+        ShuffleMode::ArtistSpread => {
+            let artists = fetch_main_artists(&tracks, session).await;
+            Shuffle::spread(tracks.into_iter().zip(artists).collect(), &mut rng)
+        }
+    }
+}
+
+/// First credited artist of each track, for spreading artists out. Tracks that can't be
+/// looked up, and episodes, stand in for their own artist.
+async fn fetch_main_artists(tracks: &[SpotifyId], session: &Session) -> Vec<SpotifyId> {
+    let spinner = Interact::start_loading_spinner("Looking up artists...");
+    let artists = stream::iter(tracks)
+        .map(|track_id| async move {
+            match Track::get(session, *track_id).await {
+                Ok(track) => track.artists.first().copied().unwrap_or(*track_id),
+                Err(_) => *track_id,
+            }
+        })
+        .buffered(FETCH_CONCURRENCY)
+        .collect()
+        .await;
+    spinner.finish_and_clear();
+    artists
+}
+
+/// Numbered "name - artist (m:ss)" lines for every track, fetched concurrently
+async fn fetch_track_listing(tracks: &[SpotifyId], session: &Session) -> Vec<String> {
+    let spinner = Interact::start_loading_spinner("Loading tracks...");
+    let track_data: Vec<Option<TrackData>> = stream::iter(tracks)
+        .map(|track_id| fetch_track_data(session, *track_id))
+        .buffered(FETCH_CONCURRENCY)
+        .collect()
+        .await;
+    spinner.finish_and_clear();
+    track_data
+        .iter()
+        .enumerate()
+        .map(|(index, td)| match td {
+            Some(td) => format!(
+                "{:>3}. {} ({})",
+                index + 1,
+                track_name(td),
+                Interact::format_duration(td.track.duration)
+            ),
+            None => format!("{:>3}. (unavailable)", index + 1),
+        })
+        .collect()
 }
 
 fn select_track_collection(
@@ -177,91 +1219,100 @@ fn select_track_collection(
     name: String,
 ) -> Option<&impl TrackCollection> {
     let keys: Vec<&String> = track_collection_map.keys().collect();
-    let selection = match name.is_empty() {
-        false => {
-            let mut matching_key = String::from("");
-            for key in keys.iter() {
-                if key.contains(&name) {
-                    matching_key = key.to_string();
-                    break;
-                }
-            }
-            matching_key
-        }
-        true => Interact::select_item(keys),
-    };
-    let selected_track_collection = track_collection_map.get(&selection);
-    selected_track_collection
+    let selection = select_name(keys, &name)?;
+    track_collection_map.get(selection)
 }
 
-async fn send_to_player(track_ids: Vec<SpotifyId>, session: Session, transmitter: Sender<Message>) {
-    let mut is_first_track = true;
-    for track_spotify_id in track_ids {
-        let track_result = Track::get(&session, track_spotify_id).await;
-        match track_result {
-            Ok(track) => {
-                let artist_result = Artist::get(&session, track.artists[0]).await;
-                match artist_result {
-                    Ok(artist) => {
-                        let message = create_message(track, artist, is_first_track);
-                        transmitter.send(message).unwrap_or_else(|err| {
-                            eprintln!("Problem sending track to player: {}", err);
-                        });
-                    }
-                    Err(_) => (),
-                }
+/// The name best matching `query`, or the user's pick from a fuzzy list if `query` is
+/// empty or matches several names about as well
+fn select_name<'a>(mut names: Vec<&'a String>, query: &str) -> Option<&'a String> {
+    let candidates: Vec<&String> = match query.is_empty() {
+        true => {
+            names.sort();
+            names
+        }
+        false => {
+            let ranked = Matching::rank(names, query);
+            if let Some(name) = Matching::clear_winner(&ranked) {
+                return Some(name);
             }
-            Err(_) => (), // TODO: How should I handle?
+            ranked.into_iter().map(|ranked| ranked.name).collect()
         }
-        is_first_track = false;
-    }
-}
-
-fn create_message(track: Track, artist: Artist, is_first_track: bool) -> Message {
-    let track_data = TrackData {
-        track,
-        artist: artist.name,
     };
-    let message = match is_first_track {
-        true => Message::StartPlaying(track_data),
-        false => Message::AddToQueue(track_data),
+    if candidates.is_empty() {
+        return None;
+    }
+    let selection = match query.is_empty() {
+        true => Interact::select_item(candidates.clone()),
+        false => Interact::select_match(candidates.clone(), query),
     };
-    message
+    candidates.into_iter().find(|name| **name == selection)
 }
 
 pub trait TrackCollection {
     fn tracks(&self) -> Vec<SpotifyId>;
-    fn shuffled_tracks(&self) -> Vec<SpotifyId>;
     fn name(&self) -> String;
 }
 
-impl TrackCollection for Album {
+impl TrackCollection for LibraryAlbum {
     fn tracks(&self) -> Vec<SpotifyId> {
-        self.tracks.clone()
+        self.album.tracks.clone()
+    }
+
+    fn name(&self) -> String {
+        self.album.name.to_string()
+    }
+}
+
+impl TrackCollection for LibraryPlaylist {
+    fn tracks(&self) -> Vec<SpotifyId> {
+        self.playlist.tracks.clone()
     }
 
-    fn shuffled_tracks(&self) -> Vec<SpotifyId> {
-        let mut rng = thread_rng();
-        let mut shuffled_tracks = self.tracks.clone();
-        shuffled_tracks.shuffle(&mut rng);
-        shuffled_tracks
+    fn name(&self) -> String {
+        self.label()
+    }
+}
+
+impl<T: TrackCollection> TrackCollection for &T {
+    fn tracks(&self) -> Vec<SpotifyId> {
+        (*self).tracks()
     }
 
     fn name(&self) -> String {
-        self.name.to_string()
+        (*self).name()
+    }
+}
+
+/// Tracks gathered on demand, like an artist's catalog or the play history
+pub struct TrackList {
+    name: String,
+    tracks: Vec<SpotifyId>,
+}
+
+impl TrackList {
+    /// A copy of any other collection's tracks and name
+    fn of(tc: &impl TrackCollection) -> TrackList {
+        TrackList {
+            name: tc.name(),
+            tracks: tc.tracks(),
+        }
     }
 }
 
-impl TrackCollection for Playlist {
+impl TrackCollection for TrackList {
     fn tracks(&self) -> Vec<SpotifyId> {
         self.tracks.clone()
     }
 
-    fn shuffled_tracks(&self) -> Vec<SpotifyId> {
-        let mut rng = thread_rng();
-        let mut shuffled_tracks = self.tracks.clone();
-        shuffled_tracks.shuffle(&mut rng);
-        shuffled_tracks
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+}
+
+impl TrackCollection for Show {
+    fn tracks(&self) -> Vec<SpotifyId> {
+        self.episodes.clone()
     }
 
     fn name(&self) -> String {
@@ -0,0 +1,169 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// One line of `ls`, with everything it can be sorted, filtered or grouped by
+pub struct ListingEntry {
+    pub label: String,
+    // Album artists, a playlist's owner or a podcast's publisher
+    pub artist: String,
+    // Whether the logged in user owns it
+    pub owned: bool,
+    // Higher for things added to the library more recently
+    pub added: usize,
+    pub track_count: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Added,
+    Tracks,
+    Artist,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(SortKey::Name),
+            "added" | "recent" => Ok(SortKey::Added),
+            "tracks" => Ok(SortKey::Tracks),
+            "artist" | "owner" => Ok(SortKey::Artist),
+            _ => Err(format!("Unknown sort order: {}", s)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ListOptions {
+    pub sort: SortKey,
+    // Only entries whose artist contains this, ignoring case
+    pub artist: Option<String>,
+    // "me" for the user's own playlists, otherwise part of the owner's name
+    pub owner: Option<String>,
+    pub limit: Option<usize>,
+    // Whether to put entries under a heading for each artist or owner
+    pub group: bool,
+}
+
+/// Filters, sorts and trims `entries` for display. Ties are broken by label so the same
+/// library always lists the same way.
+pub fn arrange(mut entries: Vec<ListingEntry>, options: &ListOptions) -> Vec<ListingEntry> {
+    if let Some(artist) = &options.artist {
+        let artist = artist.to_lowercase();
+        entries.retain(|entry| entry.artist.to_lowercase().contains(&artist));
+    }
+    match options.owner.as_deref() {
+        None => (),
+        Some("me") => entries.retain(|entry| entry.owned),
+        Some(owner) => {
+            let owner = owner.to_lowercase();
+            entries.retain(|entry| entry.artist.to_lowercase().contains(&owner));
+        }
+    }
+    entries.sort_by(|a, b| {
+        let group_order = match options.group {
+            true => compare_text(&a.artist, &b.artist),
+            false => Ordering::Equal,
+        };
+        let sort_order = match options.sort {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Added => b.added.cmp(&a.added),
+            SortKey::Tracks => b.track_count.cmp(&a.track_count),
+            SortKey::Artist => compare_text(&a.artist, &b.artist),
+        };
+        group_order
+            .then(sort_order)
+            .then_with(|| compare_text(&a.label, &b.label))
+    });
+    if let Some(limit) = options.limit {
+        entries.truncate(limit);
+    }
+    entries
+}
+
+/// Case-insensitive order, falling back to case so different strings never tie
+fn compare_text(a: &str, b: &str) -> Ordering {
+    a.to_lowercase()
+        .cmp(&b.to_lowercase())
+        .then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(label: &str, artist: &str, added: usize, track_count: usize) -> ListingEntry {
+        ListingEntry {
+            label: label.to_string(),
+            artist: artist.to_string(),
+            owned: artist == "me",
+            added,
+            track_count,
+        }
+    }
+
+    fn library() -> Vec<ListingEntry> {
+        vec![
+            entry("beta", "me", 1, 10),
+            entry("Alpha", "alice", 3, 5),
+            entry("gamma", "me", 2, 20),
+        ]
+    }
+
+    fn labels(entries: Vec<ListingEntry>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.label).collect()
+    }
+
+    #[test]
+    fn sorts_by_name_ignoring_case() {
+        let listed = arrange(library(), &ListOptions::default());
+        assert_eq!(labels(listed), vec!["Alpha", "beta", "gamma"]);
+    }
+
+    #[test]
+    fn sorts_by_recently_added_and_track_count() {
+        let options = ListOptions {
+            sort: SortKey::Added,
+            ..Default::default()
+        };
+        assert_eq!(
+            labels(arrange(library(), &options)),
+            vec!["Alpha", "gamma", "beta"]
+        );
+        let options = ListOptions {
+            sort: SortKey::Tracks,
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(labels(arrange(library(), &options)), vec!["gamma", "beta"]);
+    }
+
+    #[test]
+    fn filters_by_owner_and_artist() {
+        let options = ListOptions {
+            owner: Some(String::from("me")),
+            ..Default::default()
+        };
+        assert_eq!(labels(arrange(library(), &options)), vec!["beta", "gamma"]);
+        let options = ListOptions {
+            artist: Some(String::from("ALI")),
+            ..Default::default()
+        };
+        assert_eq!(labels(arrange(library(), &options)), vec!["Alpha"]);
+    }
+
+    #[test]
+    fn groups_by_artist_before_sorting() {
+        let options = ListOptions {
+            sort: SortKey::Tracks,
+            group: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            labels(arrange(library(), &options)),
+            vec!["Alpha", "gamma", "beta"]
+        );
+    }
+}
@@ -1,35 +1,77 @@
-use librespot::core::{cache::Cache, config::SessionConfig, session::Session};
+use librespot::core::{
+    cache::Cache,
+    config::SessionConfig,
+    session::{Session, SessionError},
+};
 use librespot::discovery::Credentials;
 use std::path::PathBuf;
 use std::process::exit;
-use std::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
+mod auth;
 mod command;
 mod config;
 mod fetch;
+mod history;
 mod interact;
 mod invoke;
+mod listing;
+mod matching;
 mod model;
 mod play;
+mod profile;
+mod progress;
+mod queue;
+mod resume;
+mod shuffle;
+mod tui;
 
+use auth as Auth;
 use command::Command;
 use config as Config;
 use fetch::Fetcher;
 use interact as Interact;
 use invoke::Invoker;
 use play::{Message, Player};
+use profile as Profile;
+use tui as Tui;
 
 use crate::interact::println;
 
 #[tokio::main]
 async fn main() {
+    let use_tui = std::env::args().any(|arg| arg == "--tui");
+    let mut invoker = start_profile(!use_tui).await;
+    if use_tui {
+        Tui::run(&mut invoker).await;
+    }
+    loop {
+        invoker.offer_resume();
+        let profile = run_commands(&mut invoker).await;
+        invoker.stop_player().await;
+        Profile::set_active(&profile);
+        invoker = start_profile(true).await;
+    }
+}
+
+/// Logs in to the active profile and loads its library
+async fn start_profile(show_spinner: bool) -> Invoker {
     let session = create_session().await;
+    if let Err(err) = Auth::authorize_if_needed().await {
+        println(&err);
+    }
     let spinner = Interact::start_session_spinner();
     let fetcher = Fetcher::new(&session).await.unwrap();
-    let (tx, rx): (Sender<Message>, Receiver<Message>) = mpsc::channel();
-    let _player = Player::new(session.clone(), rx);
-    let mut invoker = Invoker::new(session, fetcher, tx);
+    let (tx, rx): (UnboundedSender<Message>, UnboundedReceiver<Message>) =
+        mpsc::unbounded_channel();
+    let _player = Player::new(session.clone(), rx, show_spinner);
+    let invoker = Invoker::new(session, fetcher, tx);
     Interact::stop_session_spinner(spinner);
+    invoker
+}
+
+/// Runs commands until one asks to switch profiles, returning the profile to switch to
+async fn run_commands(invoker: &mut Invoker) -> String {
     loop {
         let input = Interact::get();
         if input.is_empty() {
@@ -37,33 +79,46 @@ async fn main() {
         }
 
         let command = Command::new(input);
-        let execution_result = invoker.execute(command).await;
-        match execution_result {
-            Ok(_) => (),
-            Err(_) => (),
+        let _ = invoker.execute(command).await;
+        if let Some(profile) = invoker.take_profile_switch() {
+            return profile;
         }
     }
 }
 
+/// Connects with the active profile's saved credentials, asking for a username and
+/// password when there are none or Spotify rejects them
 async fn create_session() -> Session {
     let path = get_credentials_path();
     let cache = Cache::new(Some(path), None, None, None).ok();
-    let credentials = get_credentials(&cache);
-    let session_config = SessionConfig::default();
-    let connect_result = Session::connect(session_config, credentials, cache, true).await;
-    match connect_result {
-        Result::Err(_) => {
-            println("Login Failed");
-            exit(-1);
+    let mut saved = cache.as_ref().unwrap().credentials().is_some();
+    let mut credentials = get_credentials(&cache);
+    loop {
+        let session_config = SessionConfig::default();
+        let connect_result =
+            Session::connect(session_config, credentials, cache.clone(), true).await;
+        match connect_result {
+            Ok((session, _)) => return session,
+            Err(SessionError::AuthenticationError(err)) if saved => {
+                println(&format!(
+                    "The saved login for profile {} was rejected ({})",
+                    Profile::active(),
+                    err
+                ));
+            }
+            Err(SessionError::AuthenticationError(err)) => println(&err.to_string()),
+            Err(SessionError::IoError(err)) => {
+                println(&format!("Couldn't connect to Spotify: {}", err));
+                exit(1);
+            }
         }
-        Result::Ok((session, _)) => session,
+        saved = false;
+        credentials = login_user_pass();
     }
 }
 
 fn get_credentials_path() -> PathBuf {
-    let mut local_data_path = dirs::data_local_dir().unwrap();
-    local_data_path.push(Config::APP_NAME);
-    local_data_path
+    Config::data_path()
 }
 
 fn get_credentials(cache: &Option<Cache>) -> Credentials {
@@ -78,6 +133,5 @@ fn login_user_pass() -> Credentials {
     println("Login to Spotify");
     let username = Interact::get_username();
     let password = Interact::get_password();
-    let credentials = Credentials::with_password(username, password);
-    return credentials;
+    Credentials::with_password(username, password)
 }
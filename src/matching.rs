@@ -0,0 +1,108 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+/// How well a name matches a query, best first
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum MatchKind {
+    Exact,
+    Prefix,
+    Contains,
+    // The query's characters appear in order, with gaps
+    Fuzzy,
+}
+
+pub struct RankedName<'a> {
+    pub name: &'a String,
+    pub kind: MatchKind,
+    // Higher is better, compared only between names of the same kind
+    score: i64,
+}
+
+/// Names matching `query`, ignoring case, from the best match to the worst. Names that
+/// don't match at all are left out.
+pub fn rank<'a>(names: Vec<&'a String>, query: &str) -> Vec<RankedName<'a>> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let query = query.to_lowercase();
+    let mut ranked: Vec<RankedName> = names
+        .into_iter()
+        .filter_map(|name| {
+            let lowercase_name = name.to_lowercase();
+            let (kind, score) = if lowercase_name == query {
+                (MatchKind::Exact, 0)
+            } else if lowercase_name.starts_with(&query) {
+                // Shorter names leave less unmatched
+                (MatchKind::Prefix, -(name.len() as i64))
+            } else if let Some(position) = lowercase_name.find(&query) {
+                (MatchKind::Contains, -(position as i64))
+            } else {
+                (MatchKind::Fuzzy, matcher.fuzzy_match(name, &query)?)
+            };
+            Some(RankedName { name, kind, score })
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        a.kind
+            .cmp(&b.kind)
+            .then(b.score.cmp(&a.score))
+            .then_with(|| a.name.cmp(b.name))
+    });
+    ranked
+}
+
+/// The best match if it can be picked without asking: it is the only exact, prefix or
+/// substring match of its kind. Fuzzy matches always need confirming.
+pub fn clear_winner<'a>(ranked: &[RankedName<'a>]) -> Option<&'a String> {
+    let best = ranked.first()?;
+    if best.kind == MatchKind::Fuzzy {
+        return None;
+    }
+    match ranked.get(1) {
+        Some(runner_up) if runner_up.kind == best.kind => None,
+        _ => Some(best.name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn ranked_names(names: &[String], query: &str) -> Vec<String> {
+        rank(names.iter().collect(), query)
+            .into_iter()
+            .map(|ranked| ranked.name.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn ranks_exact_then_prefix_then_substring_then_fuzzy() {
+        let library = names(&["Deep Chill", "Chill Mix", "chill", "Coding Hits", "Rock"]);
+        assert_eq!(
+            ranked_names(&library, "CHILL"),
+            vec!["chill", "Chill Mix", "Deep Chill"]
+        );
+        assert_eq!(
+            ranked_names(&library, "chl"),
+            vec!["chill", "Chill Mix", "Deep Chill"]
+        );
+    }
+
+    #[test]
+    fn picks_a_clear_winner_only() {
+        let library = names(&["Chill", "Chill Mix", "Chillhop", "Rock"]);
+        let ranked = rank(library.iter().collect(), "chill");
+        assert_eq!(clear_winner(&ranked), Some(&library[0]));
+
+        let ranked = rank(library.iter().collect(), "chill m");
+        assert_eq!(clear_winner(&ranked), Some(&library[1]));
+
+        let ranked = rank(library.iter().collect(), "chil");
+        assert_eq!(clear_winner(&ranked), None);
+
+        let ranked = rank(library.iter().collect(), "rk");
+        assert_eq!(clear_winner(&ranked), None);
+    }
+}
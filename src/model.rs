@@ -6,6 +6,22 @@ pub struct PlaylistsModel {
 #[derive(serde::Deserialize, Clone)]
 pub struct PlaylistModel {
     pub id: String,
+    pub name: String,
+    pub owner: OwnerModel,
+    pub collaborative: bool,
+    pub snapshot_id: String,
+}
+
+// What the Web API returns after changing a playlist's tracks
+#[derive(serde::Deserialize)]
+pub struct SnapshotModel {
+    pub snapshot_id: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct OwnerModel {
+    pub id: String,
+    pub display_name: Option<String>,
 }
 
 #[derive(serde::Deserialize)]
@@ -22,7 +38,6 @@ pub struct AlbumWrapperModel {
 #[derive(serde::Deserialize, Clone)]
 pub struct AlbumModel {
     pub id: String,
-    pub name: String,
     pub artists: Vec<ArtistModel>,
 }
 
@@ -31,3 +46,123 @@ pub struct ArtistModel {
     pub id: String,
     pub name: String,
 }
+
+#[derive(serde::Deserialize)]
+pub struct ArtistsModel {
+    pub artists: ArtistPageModel,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArtistPageModel {
+    pub items: Vec<ArtistModel>,
+    pub cursors: Option<CursorsModel>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct CursorsModel {
+    // Id of the last artist on the page, or none on the last page
+    pub after: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RelatedArtistsModel {
+    pub artists: Vec<ArtistModel>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ArtistAlbumsModel {
+    pub items: Vec<ArtistAlbumModel>,
+    // Link to the next page, or none on the last page
+    pub next: Option<String>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct ArtistAlbumModel {
+    pub id: String,
+    pub name: String,
+    pub album_group: String,
+    pub release_date: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ShowsModel {
+    pub items: Vec<ShowWrapperModel>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct ShowWrapperModel {
+    pub show: ShowModel,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct ShowModel {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EpisodeModel {
+    pub resume_point: Option<ResumePointModel>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResumePointModel {
+    pub fully_played: bool,
+    pub resume_position_ms: u32,
+}
+
+#[derive(serde::Serialize)]
+pub struct StatusModel {
+    pub playing: bool,
+    pub track: Option<String>,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    pub uri: Option<String>,
+    pub position_ms: u32,
+    pub duration_ms: Option<u32>,
+    pub queue_length: usize,
+    pub shuffle: bool,
+    pub repeat: String,
+    pub volume: u8,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+pub struct HistoryEntryModel {
+    // Unix seconds
+    pub played_at: i64,
+    pub uri: String,
+    pub name: String,
+    pub source: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SavedSessionModel {
+    pub source: String,
+    // The whole queue in play order, including tracks already played
+    pub tracks: Vec<String>,
+    // Index into `tracks` of the track that was playing
+    pub current: usize,
+    // The collection's own order, if the queue is shuffled
+    #[serde(default)]
+    pub unshuffled: Vec<String>,
+    pub name: String,
+    pub position_ms: u32,
+    pub shuffled: bool,
+    pub repeat: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TokenResponseModel {
+    pub access_token: String,
+    pub expires_in: i64,
+    // Only sent when a new refresh token replaces the old one
+    pub refresh_token: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct OAuthTokenModel {
+    pub access_token: String,
+    pub refresh_token: String,
+    // Unix timestamp in seconds
+    pub expires_at: i64,
+}
@@ -1,88 +1,842 @@
 use std::{
-    collections::LinkedList,
-    sync::mpsc::Receiver,
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::LazyLock,
     thread::{self},
+    time::{Duration, Instant},
 };
 
+use futures::future::join_all;
 use indicatif::ProgressBar;
 use librespot::core::session::Session;
-use librespot::metadata::Track;
+use librespot::core::spotify_id::{SpotifyAudioType, SpotifyId};
+use librespot::metadata::{Album, Artist, Episode, Metadata, Show, Track};
 use librespot::playback::player::Player as LibrePlayer;
 use librespot::playback::{
     audio_backend,
-    config::{AudioFormat, PlayerConfig},
-    mixer::NoOpVolume,
-    player::PlayerEvent,
+    config::{AudioFormat, Bitrate, PlayerConfig},
+    mixer::{softmixer::SoftMixer, Mixer, MixerConfig},
+    player::{PlayerEvent, PlayerEventChannel},
 };
+use rand::thread_rng;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc::UnboundedReceiver, oneshot, Mutex};
+use tokio::time::{self as Time, MissedTickBehavior};
 
+use crate::config as Config;
+use crate::history as History;
 use crate::interact as Interact;
+use crate::model::{HistoryEntryModel, SavedSessionModel};
+use crate::progress as Progress;
+use crate::queue::TrackQueue;
+use crate::resume as Resume;
+
+// Number of upcoming tracks to resolve metadata for ahead of time
+const QUEUE_LOOKAHEAD: usize = 2;
+// How often the queue and position are saved for `resume` while playing
+const SESSION_SAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+// Shows fetched for their episodes, which are listed and played in runs from the same show
+static SHOWS: LazyLock<Mutex<HashMap<SpotifyId, Show>>> = LazyLock::new(Default::default);
 
 pub struct Player {}
 
 impl Player {
-    pub fn new(session: Session, receiver: Receiver<Message>) -> Player {
-        let mut player = create_player(session);
-        let mut track_queue: LinkedList<TrackData> = LinkedList::new();
-        let mut events = player.get_player_event_channel();
-        let mut spinner = ProgressBar::new_spinner();
+    pub fn new(
+        session: Session,
+        receiver: UnboundedReceiver<Message>,
+        show_spinner: bool,
+    ) -> Player {
+        let player = create_player(session.clone());
+        let events = player.get_player_event_channel();
+        let mut state = PlayerState {
+            player,
+            session,
+            track_queue: TrackQueue::new(Config::settings().repeat),
+            resolved_tracks: HashMap::new(),
+            spinner: ProgressBar::hidden(),
+            show_spinner,
+            shuffled: false,
+            source: String::new(),
+            logged_current: false,
+            position: PlaybackPosition::default(),
+            volume: initial_volume(),
+        };
+        let runtime = Handle::current();
         let builder = thread::Builder::new().name("track_player".into());
-        let _thread = builder.spawn(move || loop {
-            match receiver.try_recv() {
-                Ok(message) => match message {
-                    Message::AddToQueue(track_data) => track_queue.push_back(track_data),
-                    Message::StartPlaying(track_data) => {
-                        track_queue.clear();
-                        spinner = Interact::start_player_spinner();
-                        spinner.set_message(track_data.track.name + " - " + &track_data.artist.to_string());
-                        player.load(track_data.track.id, true, 0);
+        let _thread = builder.spawn(move || {
+            runtime.block_on(run_player_loop(
+                &mut state,
+                receiver,
+                events,
+                SESSION_SAVE_INTERVAL,
+            ))
+        });
+
+        Player {}
+    }
+}
+
+/// Reacts to whatever wakes up the `track_player` thread
+trait PlayerHandler {
+    async fn handle_message(&mut self, message: Message);
+    async fn handle_event(&mut self, event: PlayerEvent);
+    async fn handle_tick(&mut self);
+}
+
+/// Sleeps until a command, a librespot event or the next tick every `tick_interval`
+/// arrives, until `Quit` or the command channel closes
+async fn run_player_loop(
+    handler: &mut impl PlayerHandler,
+    mut receiver: UnboundedReceiver<Message>,
+    mut events: PlayerEventChannel,
+    tick_interval: Duration,
+) {
+    let mut events_open = true;
+    let mut ticks = Time::interval_at(Time::Instant::now() + tick_interval, tick_interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            biased;
+            message = receiver.recv() => match message {
+                Some(Message::Quit) | None => break,
+                Some(message) => handler.handle_message(message).await,
+            },
+            event = events.recv(), if events_open => match event {
+                Some(event) => handler.handle_event(event).await,
+                None => events_open = false,
+            },
+            _ = ticks.tick() => handler.handle_tick().await,
+        }
+    }
+}
+
+/// Everything the `track_player` thread owns while it runs
+struct PlayerState {
+    player: LibrePlayer,
+    session: Session,
+    track_queue: TrackQueue,
+    resolved_tracks: HashMap<SpotifyId, TrackData>,
+    spinner: ProgressBar,
+    // The full-screen UI draws its own now-playing bar
+    show_spinner: bool,
+    shuffled: bool,
+    // Name of the collection being played, for the history
+    source: String,
+    // Whether the loaded track has made it into the history yet
+    logged_current: bool,
+    position: PlaybackPosition,
+    volume: u16,
+}
+
+/// Where playback is in the current track, as last reported by librespot
+#[derive(Default)]
+struct PlaybackPosition {
+    position_ms: u32,
+    duration_ms: u32,
+    // Set while playing so the position can be extrapolated between events
+    playing_since: Option<Instant>,
+}
+
+impl PlaybackPosition {
+    fn current_ms(&self) -> u32 {
+        let elapsed_ms = match self.playing_since {
+            Some(since) => since.elapsed().as_millis() as u32,
+            None => 0,
+        };
+        let position_ms = self.position_ms.saturating_add(elapsed_ms);
+        match self.duration_ms {
+            0 => position_ms,
+            duration_ms => position_ms.min(duration_ms),
+        }
+    }
+}
+
+impl PlayerHandler for PlayerState {
+    async fn handle_message(&mut self, message: Message) {
+        match message {
+            Message::StartPlaying {
+                tracks,
+                current,
+                unshuffled,
+                position_ms,
+                source,
+            } => {
+                self.prepare_playback(source);
+                let current_track = self.track_queue.start(tracks, current);
+                self.set_unshuffled(unshuffled);
+                self.load_playable(current_track, position_ms).await;
+            }
+            Message::StopPlaying => {
+                self.save_session();
+                self.save_episode_position();
+                self.player.stop();
+                self.track_queue.clear();
+                self.resolved_tracks.clear();
+                self.position = PlaybackPosition::default();
+                match self.show_spinner {
+                    true => Interact::stop_player_spinner(&self.spinner),
+                    false => self.spinner.finish_and_clear(),
+                }
+            }
+            Message::SetRepeat(repeat) => {
+                self.track_queue.set_repeat(repeat);
+                if let Some(track_id) = self.track_queue.current() {
+                    let track_data = self.resolve_track(track_id).await;
+                    let message = now_playing(track_data, repeat);
+                    self.spinner.set_message(message);
+                }
+            }
+            Message::SetShuffle(shuffled) => {
+                if shuffled == self.shuffled {
+                    return;
+                }
+                match shuffled {
+                    true => self.track_queue.shuffle_upcoming(&mut thread_rng()),
+                    false => self.track_queue.unshuffle_upcoming(),
+                }
+                self.shuffled = shuffled;
+                self.resolve_lookahead().await;
+            }
+            Message::Status(reply) => {
+                let _ = reply.send(self.status());
+            }
+            Message::SaveSession(reply) => {
+                self.save_session();
+                let _ = reply.send(());
+            }
+            Message::Quit => (),
+        }
+    }
+
+    async fn handle_event(&mut self, event: PlayerEvent) {
+        match event {
+            PlayerEvent::TimeToPreloadNextTrack { .. } => {
+                if let Some(track_id) = self.track_queue.peek_next() {
+                    if let Some(playable_id) = self.playable_id(track_id).await {
+                        self.player.preload(playable_id);
                     }
-                    Message::StopPlaying => {
-                        player.stop();
-                        track_queue.clear();
-                        Interact::stop_player_spinner(&spinner);
+                }
+            }
+            PlayerEvent::Playing {
+                track_id,
+                position_ms,
+                duration_ms,
+                ..
+            } => {
+                if !self.logged_current {
+                    self.log_playing(track_id);
+                }
+                self.position = PlaybackPosition {
+                    position_ms,
+                    duration_ms,
+                    playing_since: Some(Instant::now()),
+                };
+            }
+            PlayerEvent::Paused {
+                position_ms,
+                duration_ms,
+                ..
+            } => {
+                self.position = PlaybackPosition {
+                    position_ms,
+                    duration_ms,
+                    playing_since: None,
+                };
+                self.save_episode_position();
+            }
+            PlayerEvent::VolumeSet { volume } => self.volume = volume,
+            PlayerEvent::EndOfTrack { .. } => {
+                self.position.position_ms = self.position.duration_ms;
+                self.save_episode_position();
+                let next_track = self.track_queue.advance();
+                self.load_playable(next_track, 0).await;
+            }
+            PlayerEvent::Unavailable { track_id, .. } => {
+                let next = self.track_queue.peek_next();
+                match self.track_queue.current() {
+                    Some(current) if self.loaded_id(current) == track_id => {
+                        self.report_skipped(current);
+                        let next_track = self.track_queue.skip_unplayable();
+                        self.load_playable(next_track, 0).await;
                     }
-                    Message::Quit => {
-                        break;
+                    // Only the preload failed, so the current track carries on playing
+                    _ if next.map(|next| self.loaded_id(next)) == Some(track_id) => {
+                        if let Some(dropped) = self.track_queue.drop_next() {
+                            self.report_skipped(dropped);
+                        }
+                        self.resolve_lookahead().await;
                     }
-                },
-                Err(_) => (),
+                    _ => (),
+                }
             }
+            _ => (),
+        }
+    }
 
-            match events.try_recv() {
-                Ok(PlayerEvent::EndOfTrack { .. }) => {
-                    if !track_queue.is_empty() {
-                        let track_data = track_queue.pop_front().unwrap();
-                        spinner.set_message(track_data.track.name + " - " + &track_data.artist.to_string());
-                        player.load(track_data.track.id, true, 0);
-                    }
+    async fn handle_tick(&mut self) {
+        if self.position.playing_since.is_some() {
+            self.save_session();
+            self.save_episode_position();
+        }
+    }
+}
+
+impl PlayerState {
+    fn prepare_playback(&mut self, source: String) {
+        self.resolved_tracks.clear();
+        self.source = source;
+        self.spinner = match self.show_spinner {
+            true => Interact::start_player_spinner(),
+            false => ProgressBar::hidden(),
+        };
+    }
+
+    fn set_unshuffled(&mut self, unshuffled: Option<Vec<SpotifyId>>) {
+        self.shuffled = unshuffled.is_some();
+        if let Some(track_ids) = unshuffled {
+            self.track_queue.set_unshuffled(track_ids);
+        }
+    }
+
+    /// Loads `track_id` at `position_ms`, or the start of the first playable track after it
+    /// if it can't be played
+    async fn load_playable(&mut self, mut next_track: Option<SpotifyId>, mut position_ms: u32) {
+        while let Some(track_id) = next_track {
+            match self.playable_id(track_id).await {
+                Some(playable_id) => {
+                    let track_data = self.resolved_tracks.get(&track_id);
+                    let message = now_playing(track_data, self.track_queue.repeat());
+                    self.spinner.set_message(message);
+                    self.player.load(playable_id, true, position_ms);
+                    self.position = PlaybackPosition::default();
+                    self.logged_current = false;
+                    self.resolve_lookahead().await;
+                    return;
+                }
+                None => {
+                    self.report_skipped(track_id);
+                    next_track = self.track_queue.skip_unplayable();
+                    position_ms = 0;
                 }
-                Ok(_) => (),
-                Err(_) => (),
             }
-        });
+        }
+    }
 
-        Player {}
+    /// Records the track librespot just started in the local play history
+    fn log_playing(&mut self, playing_id: SpotifyId) {
+        let name = match self.track_queue.current() {
+            Some(track_id) => self.resolved_tracks.get(&track_id).map(track_name),
+            None => None,
+        };
+        let entry = HistoryEntryModel {
+            played_at: History::now(),
+            uri: playing_id.to_uri().unwrap_or_default(),
+            name: name.unwrap_or_default(),
+            source: self.source.to_owned(),
+        };
+        if let Err(err) = History::record(&entry) {
+            eprintln!("Problem writing play history: {}", err);
+        }
+        self.logged_current = true;
+    }
+
+    /// Writes the queue and position to disk for `resume`, if anything is loaded
+    fn save_session(&self) {
+        let current_track = match self.track_queue.current() {
+            Some(track_id) => track_id,
+            None => return,
+        };
+        let (track_ids, current) = self.track_queue.snapshot();
+        let to_uris = |track_ids: &[SpotifyId]| {
+            track_ids
+                .iter()
+                .filter_map(|track_id| track_id.to_uri().ok())
+                .collect()
+        };
+        let session = SavedSessionModel {
+            source: self.source.to_owned(),
+            tracks: to_uris(&track_ids),
+            current,
+            unshuffled: match self.shuffled {
+                true => to_uris(self.track_queue.unshuffled()),
+                false => Vec::new(),
+            },
+            name: match self.resolved_tracks.get(&current_track) {
+                Some(track_data) => track_name(track_data),
+                None => String::new(),
+            },
+            position_ms: self.position.current_ms(),
+            shuffled: self.shuffled,
+            repeat: self.track_queue.repeat().to_string(),
+        };
+        if let Err(err) = Resume::save(&session) {
+            eprintln!("Problem saving the session: {}", err);
+        }
+    }
+
+    /// Remembers how far into the current episode playback got, so playing it again picks
+    /// up there
+    fn save_episode_position(&self) {
+        let episode_id = match self.track_queue.current() {
+            Some(track_id) if track_id.audio_type == SpotifyAudioType::Podcast => track_id,
+            _ => return,
+        };
+        let position_ms = self.position.current_ms();
+        if let Err(err) = Progress::save(episode_id, position_ms, self.position.duration_ms) {
+            eprintln!("Problem saving the episode position: {}", err);
+        }
+    }
+
+    fn status(&self) -> PlayerStatus {
+        let resolved = |track_id: SpotifyId| self.resolved_tracks.get(&track_id).cloned();
+        PlayerStatus {
+            current: self.track_queue.current().and_then(resolved),
+            upcoming: self
+                .track_queue
+                .lookahead(QUEUE_LOOKAHEAD)
+                .into_iter()
+                .filter_map(resolved)
+                .collect(),
+            queue_length: self.track_queue.remaining(),
+            position_ms: self.position.current_ms(),
+            playing: self.position.playing_since.is_some(),
+            shuffled: self.shuffled,
+            repeat: self.track_queue.repeat(),
+            volume: self.volume,
+        }
+    }
+
+    /// Id to hand to librespot for a queued track, which may be a relinked alternative, or
+    /// `None` if the track and its alternatives are all unavailable. A track whose metadata
+    /// can't be fetched is loaded as it is, and librespot reports it if it can't be played.
+    async fn playable_id(&mut self, track_id: SpotifyId) -> Option<SpotifyId> {
+        match self.resolve_track(track_id).await {
+            Some(track_data) if !track_data.track.available => None,
+            Some(track_data) => Some(track_data.track.id),
+            None => Some(track_id),
+        }
+    }
+
+    /// Id librespot was given for a queued track, see `playable_id`
+    fn loaded_id(&self, track_id: SpotifyId) -> SpotifyId {
+        match self.resolved_tracks.get(&track_id) {
+            Some(track_data) => track_data.track.id,
+            None => track_id,
+        }
+    }
+
+    fn report_skipped(&self, track_id: SpotifyId) {
+        let name = match self.resolved_tracks.get(&track_id) {
+            Some(track_data) => track_name(track_data),
+            None => track_id.to_uri().unwrap_or_default(),
+        };
+        Interact::print_skipped(&self.spinner, &name);
+    }
+
+    /// Fetches the metadata needed to display a track, reusing anything resolved ahead of time
+    async fn resolve_track(&mut self, track_id: SpotifyId) -> Option<&TrackData> {
+        if !self.resolved_tracks.contains_key(&track_id) {
+            let track_data = fetch_track_data(&self.session, track_id).await?;
+            self.resolved_tracks.insert(track_id, track_data);
+        }
+        self.resolved_tracks.get(&track_id)
+    }
+
+    async fn resolve_lookahead(&mut self) {
+        for track_id in self.track_queue.lookahead(QUEUE_LOOKAHEAD) {
+            self.resolve_track(track_id).await;
+        }
     }
 }
 
 fn create_player(session: Session) -> LibrePlayer {
-    let player_config = PlayerConfig::default();
+    let settings = Config::settings();
+    let player_config = PlayerConfig {
+        bitrate: Bitrate::from_str(&settings.bitrate.to_string()).unwrap_or_default(),
+        ..PlayerConfig::default()
+    };
     let audio_format = AudioFormat::default();
-    let backend = audio_backend::find(None).unwrap();
-    let result = LibrePlayer::new(player_config, session, Box::new(NoOpVolume), move || {
-        backend(None, audio_format)
+    let backend = audio_backend::find(settings.backend.clone()).unwrap_or_else(|| {
+        Interact::print_warning(&format!(
+            "Unknown audio backend {}, using the default",
+            settings.backend.as_deref().unwrap_or_default()
+        ));
+        audio_backend::find(None).unwrap()
+    });
+    let device = settings.device.clone();
+    let mixer = SoftMixer::open(MixerConfig::default());
+    mixer.set_volume(initial_volume());
+    let result = LibrePlayer::new(player_config, session, mixer.get_soft_volume(), move || {
+        backend(device, audio_format)
     });
     result.0
 }
 
+// The configured volume on the mixer's 0..=u16::MAX scale
+fn initial_volume() -> u16 {
+    (Config::settings().volume as u32 * u16::MAX as u32 / 100) as u16
+}
+
+pub async fn fetch_track_data(session: &Session, track_id: SpotifyId) -> Option<TrackData> {
+    if track_id.audio_type == SpotifyAudioType::Podcast {
+        return fetch_episode_data(session, track_id).await;
+    }
+    let mut track = Track::get(session, track_id).await.ok()?;
+    if !track.available {
+        if let Some(alternative) = fetch_available_alternative(session, &track).await {
+            track = alternative;
+        }
+    }
+    let artist_lookups = join_all(track.artists.iter().map(|id| Artist::get(session, *id)));
+    let (artists, album) = tokio::join!(artist_lookups, Album::get(session, track.album));
+    let artists: Vec<Artist> = artists.into_iter().filter_map(Result::ok).collect();
+    if artists.is_empty() {
+        return None;
+    }
+    // Anyone not credited on the album itself is treated as a featured artist. Without the
+    // album there's no telling, so everyone counts as a main artist.
+    let (main_artists, featured_artists): (Vec<Artist>, Vec<Artist>) = match &album {
+        Ok(album) => artists
+            .iter()
+            .cloned()
+            .partition(|artist| album.artists.contains(&artist.id)),
+        Err(_) => (Vec::new(), Vec::new()),
+    };
+    let (main_artists, featured_artists) = match main_artists.is_empty() {
+        true => (artists, Vec::new()),
+        false => (main_artists, featured_artists),
+    };
+    Some(TrackData {
+        track,
+        artists: artist_names(main_artists),
+        featured_artists: artist_names(featured_artists),
+        album: album.map(|album| album.name).unwrap_or_default(),
+    })
+}
+
+/// Episodes are dressed up as tracks, with the show's publisher as the artist, so the
+/// queue and displays don't need to tell them apart
+async fn fetch_episode_data(session: &Session, episode_id: SpotifyId) -> Option<TrackData> {
+    let episode = Episode::get(session, episode_id).await.ok()?;
+    let show = fetch_show(session, episode.show).await?;
+    let track = Track {
+        id: episode.id,
+        name: episode.name,
+        duration: episode.duration,
+        album: episode.show,
+        artists: Vec::new(),
+        files: episode.files,
+        alternatives: Vec::new(),
+        available: episode.available,
+    };
+    Some(TrackData {
+        track,
+        artists: vec![show.publisher],
+        featured_artists: Vec::new(),
+        album: show.name,
+    })
+}
+
+/// Looks each show up once, however many of its episodes are being fetched at the same time
+async fn fetch_show(session: &Session, show_id: SpotifyId) -> Option<Show> {
+    let mut shows = SHOWS.lock().await;
+    if let Some(show) = shows.get(&show_id) {
+        return Some(show.clone());
+    }
+    let show = Show::get(session, show_id).await.ok()?;
+    shows.insert(show_id, show.clone());
+    Some(show)
+}
+
+fn artist_names(artists: Vec<Artist>) -> Vec<String> {
+    artists.into_iter().map(|artist| artist.name).collect()
+}
+
+/// Region-locked tracks often have a playable relinked version listed as an alternative
+async fn fetch_available_alternative(session: &Session, track: &Track) -> Option<Track> {
+    for alternative_id in &track.alternatives {
+        if let Ok(alternative) = Track::get(session, *alternative_id).await {
+            if alternative.available {
+                return Some(alternative);
+            }
+        }
+    }
+    None
+}
+
+pub fn track_name(track_data: &TrackData) -> String {
+    track_data.track.name.to_owned() + " - " + &track_data.artist_names()
+}
+
+/// Formats credits as "A, B feat. C"
+pub fn format_artists(artists: &[String], featured_artists: &[String]) -> String {
+    let mut names = artists.join(", ");
+    if !featured_artists.is_empty() {
+        names += " feat. ";
+        names += &featured_artists.join(", ");
+    }
+    names
+}
+
+fn now_playing(track_data: Option<&TrackData>, repeat: Repeat) -> String {
+    let mut message = match track_data {
+        Some(td) => track_name(td),
+        None => String::from("Unknown track"),
+    };
+    if repeat != Repeat::Off {
+        message += &format!(" [repeat {}]", repeat);
+    }
+    message
+}
+
+#[derive(Debug)]
 pub enum Message {
-    StartPlaying(TrackData),
+    StartPlaying {
+        tracks: Vec<SpotifyId>,
+        // Index of the track to start with; the ones before it count as played
+        current: usize,
+        // The collection's own order when `tracks` is shuffled
+        unshuffled: Option<Vec<SpotifyId>>,
+        // Where to start in the current track
+        position_ms: u32,
+        // Collection name recorded in the play history
+        source: String,
+    },
     StopPlaying,
-    AddToQueue(TrackData),
+    SetRepeat(Repeat),
+    // Reorders the tracks still to come without touching the current one
+    SetShuffle(bool),
+    Status(oneshot::Sender<PlayerStatus>),
+    // Replies once the session has been written for `resume`
+    SaveSession(oneshot::Sender<()>),
     Quit,
 }
 
+#[derive(Clone, Debug)]
 pub struct TrackData {
     pub track: Track,
-    pub artist: String,
-}
\ No newline at end of file
+    pub artists: Vec<String>,
+    pub featured_artists: Vec<String>,
+    pub album: String,
+}
+
+impl TrackData {
+    pub fn artist_names(&self) -> String {
+        format_artists(&self.artists, &self.featured_artists)
+    }
+}
+
+/// Snapshot of the player thread's state, for display
+#[derive(Clone, Debug)]
+pub struct PlayerStatus {
+    pub current: Option<TrackData>,
+    // Only the tracks whose metadata has already been resolved
+    pub upcoming: Vec<TrackData>,
+    // Tracks left after the current one, resolved or not
+    pub queue_length: usize,
+    pub position_ms: u32,
+    pub playing: bool,
+    pub shuffled: bool,
+    pub repeat: Repeat,
+    // librespot's 0..=u16::MAX scale
+    pub volume: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Repeat {
+    Off,
+    One,
+    All,
+}
+
+impl Repeat {
+    /// Next mode in the off -> all -> one cycle used by the playback hotkey
+    pub fn next(self) -> Repeat {
+        match self {
+            Repeat::Off => Repeat::All,
+            Repeat::All => Repeat::One,
+            Repeat::One => Repeat::Off,
+        }
+    }
+}
+
+impl FromStr for Repeat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Repeat::Off),
+            "one" => Ok(Repeat::One),
+            "all" => Ok(Repeat::All),
+            _ => Err(format!("Unknown repeat mode: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Repeat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mode = match self {
+            Repeat::Off => "off",
+            Repeat::One => "one",
+            Repeat::All => "all",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[derive(Default)]
+    struct RecordingHandler {
+        handled: Vec<String>,
+        // Commands to send back into the loop once an event has been handled
+        reply_to_event: Option<mpsc::UnboundedSender<Message>>,
+        reply_to_tick: Option<mpsc::UnboundedSender<Message>>,
+    }
+
+    impl PlayerHandler for RecordingHandler {
+        async fn handle_message(&mut self, message: Message) {
+            let label = match message {
+                Message::StartPlaying { tracks, .. } => format!("start {}", tracks.len()),
+                Message::StopPlaying => String::from("stop"),
+                Message::SetRepeat(repeat) => format!("repeat {}", repeat),
+                Message::SetShuffle(shuffled) => format!("shuffle {}", shuffled),
+                Message::Status(_) => String::from("status"),
+                Message::SaveSession(_) => String::from("save"),
+                Message::Quit => String::from("quit"),
+            };
+            self.handled.push(label);
+        }
+
+        async fn handle_event(&mut self, event: PlayerEvent) {
+            let label = match event {
+                PlayerEvent::EndOfTrack { .. } => "end of track",
+                _ => "other event",
+            };
+            self.handled.push(String::from(label));
+            if let Some(tx) = self.reply_to_event.take() {
+                tx.send(Message::StopPlaying).unwrap();
+                tx.send(Message::Quit).unwrap();
+            }
+        }
+
+        async fn handle_tick(&mut self) {
+            self.handled.push(String::from("tick"));
+            if let Some(tx) = self.reply_to_tick.take() {
+                tx.send(Message::Quit).unwrap();
+            }
+        }
+    }
+
+    // Long enough that no tick fires during a test unless it asks for one
+    const NO_TICKS: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn formats_featured_artists_after_main_artists() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(format_artists(&names(&["A"]), &[]), "A");
+        assert_eq!(
+            format_artists(&names(&["A", "B"]), &names(&["C"])),
+            "A, B feat. C"
+        );
+    }
+
+    fn track_id(id: u128) -> SpotifyId {
+        SpotifyId {
+            id,
+            audio_type: SpotifyAudioType::Track,
+        }
+    }
+
+    fn end_of_track() -> PlayerEvent {
+        PlayerEvent::EndOfTrack {
+            play_request_id: 0,
+            track_id: track_id(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn handles_messages_in_order_until_quit() {
+        let mut handler = RecordingHandler::default();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (_event_tx, event_rx) = mpsc::unbounded_channel();
+        tx.send(Message::StartPlaying {
+            tracks: vec![track_id(1), track_id(2)],
+            current: 0,
+            unshuffled: None,
+            position_ms: 0,
+            source: String::from("test"),
+        })
+        .unwrap();
+        tx.send(Message::SetRepeat(Repeat::All)).unwrap();
+        tx.send(Message::Quit).unwrap();
+        tx.send(Message::StopPlaying).unwrap();
+
+        run_player_loop(&mut handler, rx, event_rx, NO_TICKS).await;
+
+        assert_eq!(handler.handled, vec!["start 2", "repeat all"]);
+    }
+
+    #[tokio::test]
+    async fn stops_when_command_channel_closes() {
+        let mut handler = RecordingHandler::default();
+        let (tx, rx) = mpsc::unbounded_channel::<Message>();
+        let (_event_tx, event_rx) = mpsc::unbounded_channel();
+        drop(tx);
+
+        run_player_loop(&mut handler, rx, event_rx, NO_TICKS).await;
+
+        assert!(handler.handled.is_empty());
+    }
+
+    #[tokio::test]
+    async fn wakes_up_for_player_events() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel();
+        let mut handler = RecordingHandler {
+            reply_to_event: Some(tx),
+            ..Default::default()
+        };
+        event_tx.send(end_of_track()).unwrap();
+
+        run_player_loop(&mut handler, rx, event_rx, NO_TICKS).await;
+
+        assert_eq!(handler.handled, vec!["end of track", "stop"]);
+    }
+
+    #[tokio::test]
+    async fn keeps_handling_messages_after_event_channel_closes() {
+        let mut handler = RecordingHandler::default();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (event_tx, event_rx) = mpsc::unbounded_channel::<PlayerEvent>();
+
+        let player_loop = run_player_loop(&mut handler, rx, event_rx, NO_TICKS);
+        let driver = async {
+            drop(event_tx);
+            tokio::task::yield_now().await;
+            tx.send(Message::SetRepeat(Repeat::One)).unwrap();
+            tx.send(Message::Quit).unwrap();
+        };
+        tokio::join!(player_loop, driver);
+
+        assert_eq!(handler.handled, vec!["repeat one"]);
+    }
+
+    #[tokio::test]
+    async fn wakes_up_for_ticks_when_idle() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (_event_tx, event_rx) = mpsc::unbounded_channel();
+        let mut handler = RecordingHandler {
+            reply_to_tick: Some(tx),
+            ..Default::default()
+        };
+
+        run_player_loop(&mut handler, rx, event_rx, Duration::from_millis(1)).await;
+
+        assert_eq!(handler.handled, vec!["tick"]);
+    }
+}
@@ -0,0 +1,128 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::auth as Auth;
+use crate::config as Config;
+use crate::history as History;
+use crate::progress as Progress;
+use crate::resume as Resume;
+
+pub const DEFAULT_PROFILE: &str = "default";
+// Other profiles live in a directory each under here; the default profile uses the
+// cache directory itself so existing logins keep working
+const PROFILES_DIR: &str = "profiles";
+const CREDENTIALS_FILE: &str = "credentials.json";
+
+// Empty until first asked for, then the `profile` setting until a switch
+static ACTIVE: Mutex<String> = Mutex::new(String::new());
+
+/// The profile whose credentials and library data are in use
+pub fn active() -> String {
+    let mut active = ACTIVE.lock().unwrap();
+    if active.is_empty() {
+        *active = Config::settings().profile.to_owned();
+    }
+    active.to_owned()
+}
+
+pub fn set_active(name: &str) {
+    *ACTIVE.lock().unwrap() = name.to_string();
+}
+
+/// The directory holding `name`'s credentials and library data
+pub fn path(name: &str) -> PathBuf {
+    let mut path = Config::settings().cache_dir.to_owned();
+    if name != DEFAULT_PROFILE {
+        path.push(PROFILES_DIR);
+        path.push(name);
+    }
+    path
+}
+
+/// Whether `name` has saved credentials to log in with
+pub fn exists(name: &str) -> bool {
+    let mut credentials_path = path(name);
+    credentials_path.push(CREDENTIALS_FILE);
+    credentials_path.exists()
+}
+
+/// Every profile with saved credentials, default first and the rest by name
+pub fn list() -> Vec<String> {
+    let mut profiles_path = Config::settings().cache_dir.to_owned();
+    profiles_path.push(PROFILES_DIR);
+    let mut names: Vec<String> = fs::read_dir(profiles_path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| is_valid_name(name) && name != DEFAULT_PROFILE)
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names.insert(0, String::from(DEFAULT_PROFILE));
+    names.retain(|name| exists(name));
+    names
+}
+
+/// Deletes `name`'s saved credentials and token, so the next login asks again
+pub fn forget_login(name: &str) -> io::Result<()> {
+    remove_files(name, &[CREDENTIALS_FILE, Auth::TOKEN_FILE])
+}
+
+/// Deletes everything kept for `name`: its login, play history, saved session and episode
+/// positions
+pub fn forget(name: &str) -> io::Result<()> {
+    remove_files(
+        name,
+        &[
+            CREDENTIALS_FILE,
+            Auth::TOKEN_FILE,
+            History::HISTORY_FILE,
+            Resume::SESSION_FILE,
+            Progress::PROGRESS_FILE,
+        ],
+    )?;
+    if name != DEFAULT_PROFILE {
+        // Leaves the directory alone if something else was put in it
+        let _ = fs::remove_dir(path(name));
+    }
+    Ok(())
+}
+
+// Only removes the files the app itself writes, since the cache directory is configurable
+fn remove_files(name: &str, files: &[&str]) -> io::Result<()> {
+    for file in files {
+        let mut file_path = path(name);
+        file_path.push(file);
+        match fs::remove_file(file_path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Profile names become directory names, so only letters, digits, `-` and `_` are allowed
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_must_be_safe_as_directory_names() {
+        assert!(is_valid_name("work"));
+        assert!(is_valid_name("family_2-shared"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../default"));
+        assert!(!is_valid_name("my profile"));
+    }
+}
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use librespot::core::spotify_id::SpotifyId;
+
+use crate::config as Config;
+
+pub const PROGRESS_FILE: &str = "episodes.json";
+// Episodes stopped this close to the end count as finished and start over next time
+const FINISHED_MARGIN_MS: u32 = 30_000;
+
+fn progress_path() -> PathBuf {
+    let mut path = Config::data_path();
+    path.push(PROGRESS_FILE);
+    path
+}
+
+// Position in milliseconds by episode URI
+fn load_all() -> HashMap<String, u32> {
+    let contents = fs::read_to_string(progress_path()).unwrap_or_default();
+    serde_json::from_str(&contents).unwrap_or_default()
+}
+
+/// Remembers how far into `episode_id` playback got, or forgets it once it is finished
+pub fn save(episode_id: SpotifyId, position_ms: u32, duration_ms: u32) -> io::Result<()> {
+    let uri = match episode_id.to_uri() {
+        Ok(uri) => uri,
+        Err(_) => return Ok(()),
+    };
+    let mut positions = load_all();
+    match is_finished(position_ms, duration_ms) {
+        true => positions.remove(&uri),
+        false => positions.insert(uri, position_ms),
+    };
+    let path = progress_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(&positions)?)
+}
+
+/// Where playback of `episode_id` last got to here, if it was started and not finished
+pub fn load(episode_id: SpotifyId) -> Option<u32> {
+    let uri = episode_id.to_uri().ok()?;
+    load_all()
+        .get(&uri)
+        .copied()
+        .filter(|position_ms| *position_ms > 0)
+}
+
+fn is_finished(position_ms: u32, duration_ms: u32) -> bool {
+    duration_ms > 0 && position_ms.saturating_add(FINISHED_MARGIN_MS) >= duration_ms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn episodes_stopped_near_the_end_are_finished() {
+        assert!(!is_finished(60_000, 600_000));
+        assert!(is_finished(580_000, 600_000));
+        assert!(is_finished(600_000, 600_000));
+        // Nothing to compare against before librespot reports the duration
+        assert!(!is_finished(60_000, 0));
+    }
+}
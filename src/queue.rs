@@ -0,0 +1,306 @@
+use std::collections::{HashMap, VecDeque};
+
+use librespot::core::spotify_id::SpotifyId;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::play::Repeat;
+
+/// Order in which the player walks through a collection's tracks
+pub struct TrackQueue {
+    current: Option<SpotifyId>,
+    upcoming: VecDeque<SpotifyId>,
+    played: Vec<SpotifyId>,
+    // The collection's own order, for turning shuffle off again
+    unshuffled: Vec<SpotifyId>,
+    repeat: Repeat,
+}
+
+impl TrackQueue {
+    pub fn new(repeat: Repeat) -> TrackQueue {
+        TrackQueue {
+            current: None,
+            upcoming: VecDeque::new(),
+            played: Vec::new(),
+            unshuffled: Vec::new(),
+            repeat,
+        }
+    }
+
+    /// Replaces the queue with `track_ids`, treating everything before `current` as already
+    /// played, and returns the track at `current`
+    pub fn start(&mut self, mut track_ids: Vec<SpotifyId>, current: usize) -> Option<SpotifyId> {
+        self.clear();
+        self.unshuffled = track_ids.clone();
+        let current = current.min(track_ids.len());
+        self.upcoming.extend(track_ids.drain(current..));
+        self.played = track_ids;
+        self.current = self.upcoming.pop_front();
+        self.current
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.upcoming.clear();
+        self.played.clear();
+        self.unshuffled.clear();
+    }
+
+    /// Sets the order `unshuffle_upcoming` goes back to, when the queue was started shuffled
+    pub fn set_unshuffled(&mut self, track_ids: Vec<SpotifyId>) {
+        self.unshuffled = track_ids;
+    }
+
+    pub fn unshuffled(&self) -> &[SpotifyId] {
+        &self.unshuffled
+    }
+
+    /// Puts the tracks still to come in random order, leaving the current one playing
+    pub fn shuffle_upcoming<R: Rng>(&mut self, rng: &mut R) {
+        self.upcoming.make_contiguous().shuffle(rng);
+    }
+
+    /// Puts the tracks still to come back in the collection's order, carrying on from the
+    /// current track and wrapping around to the ones before it
+    pub fn unshuffle_upcoming(&mut self) {
+        let mut positions: HashMap<SpotifyId, usize> = HashMap::new();
+        for (position, track_id) in self.unshuffled.iter().enumerate() {
+            positions.entry(*track_id).or_insert(position);
+        }
+        let current_position = self
+            .current
+            .and_then(|track_id| positions.get(&track_id).copied());
+        let unknown = self.unshuffled.len();
+        self.upcoming.make_contiguous().sort_by_key(|track_id| {
+            let position = positions.get(track_id).copied().unwrap_or(unknown);
+            let wrapped = match current_position {
+                Some(current) => position <= current && position != unknown,
+                None => false,
+            };
+            (wrapped, position)
+        });
+    }
+
+    /// The whole queue in play order and the index of the current track in it, as
+    /// accepted by `start`
+    pub fn snapshot(&self) -> (Vec<SpotifyId>, usize) {
+        let mut track_ids = self.played.clone();
+        track_ids.extend(self.current);
+        track_ids.extend(self.upcoming.iter());
+        (track_ids, self.played.len())
+    }
+
+    /// Moves on after the current track ends and returns the track to play next
+    pub fn advance(&mut self) -> Option<SpotifyId> {
+        if self.repeat == Repeat::One {
+            return self.current;
+        }
+        if let Some(track_id) = self.current.take() {
+            self.played.push(track_id);
+        }
+        if self.upcoming.is_empty() && self.repeat == Repeat::All {
+            self.upcoming.extend(self.played.drain(..));
+        }
+        self.current = self.upcoming.pop_front();
+        self.current
+    }
+
+    /// Drops the current track from the queue for good and returns the track to play next
+    pub fn skip_unplayable(&mut self) -> Option<SpotifyId> {
+        self.current = None;
+        if self.upcoming.is_empty() && self.repeat == Repeat::All {
+            self.upcoming.extend(self.played.drain(..));
+        }
+        self.current = self.upcoming.pop_front();
+        self.current
+    }
+
+    /// Drops the track `advance` would move on to from the queue for good, leaving the
+    /// current one playing, and returns it
+    pub fn drop_next(&mut self) -> Option<SpotifyId> {
+        if self.repeat == Repeat::One {
+            return None;
+        }
+        match self.upcoming.pop_front() {
+            Some(track_id) => Some(track_id),
+            None if self.repeat == Repeat::All && !self.played.is_empty() => {
+                Some(self.played.remove(0))
+            }
+            None => None,
+        }
+    }
+
+    /// Track that `advance` will return, without moving the queue
+    pub fn peek_next(&self) -> Option<SpotifyId> {
+        self.lookahead(1).first().copied()
+    }
+
+    /// Up to `count` tracks that will play after the current one
+    pub fn lookahead(&self, count: usize) -> Vec<SpotifyId> {
+        if self.repeat == Repeat::One {
+            return self.current.into_iter().collect();
+        }
+        let mut tracks: Vec<SpotifyId> = self.upcoming.iter().take(count).copied().collect();
+        if self.repeat == Repeat::All {
+            let wrapped = self.played.iter().chain(self.current.iter());
+            tracks.extend(wrapped.take(count - tracks.len()));
+        }
+        tracks
+    }
+
+    /// Number of tracks still to come before the queue runs out or wraps
+    pub fn remaining(&self) -> usize {
+        self.upcoming.len()
+    }
+
+    pub fn current(&self) -> Option<SpotifyId> {
+        self.current
+    }
+
+    pub fn repeat(&self) -> Repeat {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: Repeat) {
+        self.repeat = repeat;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use librespot::core::spotify_id::SpotifyAudioType;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn track_id(id: u128) -> SpotifyId {
+        SpotifyId {
+            id,
+            audio_type: SpotifyAudioType::Track,
+        }
+    }
+
+    fn started_queue(repeat: Repeat) -> TrackQueue {
+        let mut track_queue = TrackQueue::new(repeat);
+        track_queue.start(vec![track_id(1), track_id(2), track_id(3)], 0);
+        track_queue
+    }
+
+    #[test]
+    fn plays_through_once_without_repeat() {
+        let mut track_queue = started_queue(Repeat::Off);
+        assert_eq!(track_queue.current(), Some(track_id(1)));
+        assert_eq!(track_queue.advance(), Some(track_id(2)));
+        assert_eq!(track_queue.advance(), Some(track_id(3)));
+        assert_eq!(track_queue.peek_next(), None);
+        assert_eq!(track_queue.advance(), None);
+    }
+
+    #[test]
+    fn repeat_one_replays_current_track() {
+        let mut track_queue = started_queue(Repeat::One);
+        assert_eq!(track_queue.peek_next(), Some(track_id(1)));
+        assert_eq!(track_queue.advance(), Some(track_id(1)));
+    }
+
+    #[test]
+    fn repeat_all_wraps_to_first_track() {
+        let mut track_queue = started_queue(Repeat::All);
+        track_queue.advance();
+        track_queue.advance();
+        assert_eq!(track_queue.peek_next(), Some(track_id(1)));
+        assert_eq!(track_queue.advance(), Some(track_id(1)));
+        assert_eq!(
+            track_queue.lookahead(3),
+            vec![track_id(2), track_id(3), track_id(1)]
+        );
+    }
+
+    #[test]
+    fn unplayable_tracks_are_not_repeated() {
+        let mut track_queue = started_queue(Repeat::All);
+        assert_eq!(track_queue.skip_unplayable(), Some(track_id(2)));
+        assert_eq!(track_queue.advance(), Some(track_id(3)));
+        assert_eq!(track_queue.advance(), Some(track_id(2)));
+    }
+
+    #[test]
+    fn a_failed_preload_drops_only_the_next_track() {
+        let mut track_queue = started_queue(Repeat::All);
+        assert_eq!(track_queue.drop_next(), Some(track_id(2)));
+        assert_eq!(track_queue.current(), Some(track_id(1)));
+        assert_eq!(track_queue.advance(), Some(track_id(3)));
+        assert_eq!(track_queue.advance(), Some(track_id(1)));
+
+        assert_eq!(track_queue.drop_next(), Some(track_id(3)));
+        assert_eq!(track_queue.current(), Some(track_id(1)));
+        assert_eq!(track_queue.drop_next(), None);
+        assert_eq!(track_queue.advance(), Some(track_id(1)));
+    }
+
+    #[test]
+    fn skipping_ends_with_the_queue() {
+        let mut track_queue = TrackQueue::new(Repeat::One);
+        track_queue.start(vec![track_id(1)], 0);
+        assert_eq!(track_queue.skip_unplayable(), None);
+        assert_eq!(track_queue.current(), None);
+    }
+
+    #[test]
+    fn shuffles_and_unshuffles_only_what_is_left() {
+        let mut track_queue = TrackQueue::new(Repeat::Off);
+        track_queue.start((1..=6).map(track_id).collect(), 0);
+        track_queue.advance();
+        track_queue.shuffle_upcoming(&mut StdRng::seed_from_u64(4));
+        assert_eq!(track_queue.current(), Some(track_id(2)));
+        let mut upcoming = track_queue.lookahead(10);
+        upcoming.sort_by_key(|track_id| track_id.id);
+        assert_eq!(upcoming, (3..=6).map(track_id).collect::<Vec<_>>());
+
+        track_queue.unshuffle_upcoming();
+        assert_eq!(
+            track_queue.lookahead(10),
+            (3..=6).map(track_id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn unshuffling_carries_on_after_the_current_track() {
+        let mut track_queue = TrackQueue::new(Repeat::Off);
+        let shuffled = [4, 1, 5, 2, 3].into_iter().map(track_id).collect();
+        track_queue.start(shuffled, 0);
+        track_queue.set_unshuffled((1..=5).map(track_id).collect());
+        track_queue.advance();
+        assert_eq!(track_queue.current(), Some(track_id(1)));
+
+        track_queue.unshuffle_upcoming();
+        assert_eq!(
+            track_queue.lookahead(10),
+            vec![track_id(2), track_id(3), track_id(5)]
+        );
+    }
+
+    #[test]
+    fn starting_mid_collection_counts_earlier_tracks_as_played() {
+        let mut track_queue = TrackQueue::new(Repeat::All);
+        let track_ids: Vec<SpotifyId> = (1..=4).map(track_id).collect();
+        assert_eq!(track_queue.start(track_ids.clone(), 2), Some(track_id(3)));
+        assert_eq!(track_queue.snapshot(), (track_ids, 2));
+        assert_eq!(track_queue.advance(), Some(track_id(4)));
+        assert_eq!(track_queue.advance(), Some(track_id(1)));
+    }
+
+    #[test]
+    fn restores_a_snapshot_mid_queue() {
+        let mut track_queue = started_queue(Repeat::All);
+        track_queue.advance();
+        let (track_ids, current) = track_queue.snapshot();
+        assert_eq!(track_ids, vec![track_id(1), track_id(2), track_id(3)]);
+        assert_eq!(current, 1);
+
+        let mut restored = TrackQueue::new(Repeat::All);
+        assert_eq!(restored.start(track_ids, current), Some(track_id(2)));
+        assert_eq!(restored.advance(), Some(track_id(3)));
+        assert_eq!(restored.advance(), Some(track_id(1)));
+    }
+}
@@ -0,0 +1,29 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config as Config;
+use crate::model::SavedSessionModel;
+
+pub const SESSION_FILE: &str = "session.json";
+
+fn session_path() -> PathBuf {
+    let mut path = Config::data_path();
+    path.push(SESSION_FILE);
+    path
+}
+
+/// Overwrites the saved session with `session`
+pub fn save(session: &SavedSessionModel) -> io::Result<()> {
+    let path = session_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(session)?)
+}
+
+/// The last saved session, if there is one and it can be read
+pub fn load() -> Option<SavedSessionModel> {
+    let contents = fs::read_to_string(session_path()).ok()?;
+    serde_json::from_str(&contents).ok()
+}
@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+/// How `play ... shuffle` orders a collection
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShuffleMode {
+    // Any order at all
+    Random,
+    // Random, but avoiding the same artist twice in a row
+    ArtistSpread,
+}
+
+/// Shuffles `items` so that neighbours have different keys wherever possible. Each step
+/// picks a key at random, weighted by how many of its items are left, except the key just
+/// played. A key with more than half the remaining items is always picked so it can't be
+/// left bunched up at the end.
+pub fn spread<T, K: Eq + Hash, R: Rng>(items: Vec<(T, K)>, rng: &mut R) -> Vec<T> {
+    // Grouped in order of first appearance so the result only depends on the rng
+    let mut group_index: HashMap<K, usize> = HashMap::new();
+    let mut groups: Vec<Vec<T>> = Vec::new();
+    for (item, key) in items {
+        let index = *group_index.entry(key).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[index].push(item);
+    }
+    for group in groups.iter_mut() {
+        group.shuffle(rng);
+    }
+
+    let mut remaining: usize = groups.iter().map(Vec::len).sum();
+    let mut spread = Vec::with_capacity(remaining);
+    let mut last_group: Option<usize> = None;
+    while remaining > 0 {
+        let mut candidates: Vec<usize> = (0..groups.len())
+            .filter(|&index| !groups[index].is_empty() && Some(index) != last_group)
+            .collect();
+        if candidates.is_empty() {
+            candidates.extend(last_group);
+        }
+        let largest = candidates
+            .iter()
+            .copied()
+            .max_by_key(|&index| (groups[index].len(), std::cmp::Reverse(index)))
+            .unwrap_or_default();
+        let chosen = match groups[largest].len() * 2 > remaining {
+            true => largest,
+            false => {
+                let mut pick = rng.gen_range(0..candidates.iter().map(|&i| groups[i].len()).sum());
+                let mut chosen = candidates[0];
+                for &index in &candidates {
+                    if pick < groups[index].len() {
+                        chosen = index;
+                        break;
+                    }
+                    pick -= groups[index].len();
+                }
+                chosen
+            }
+        };
+        if let Some(item) = groups[chosen].pop() {
+            spread.push(item);
+        }
+        remaining -= 1;
+        last_group = Some(chosen);
+    }
+    spread
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn tracks_by(artists: &str) -> Vec<(usize, char)> {
+        artists.chars().enumerate().collect()
+    }
+
+    fn artists_of(order: &[usize], artists: &str) -> String {
+        let artists: Vec<char> = artists.chars().collect();
+        order.iter().map(|&index| artists[index]).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_spread() {
+        let artists = "AAABBBCCDE";
+        let first = spread(tracks_by(artists), &mut StdRng::seed_from_u64(7));
+        let second = spread(tracks_by(artists), &mut StdRng::seed_from_u64(7));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn keeps_every_track() {
+        let artists = "AAABBBCCDE";
+        let mut order = spread(tracks_by(artists), &mut StdRng::seed_from_u64(1));
+        order.sort();
+        assert_eq!(order, (0..artists.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn never_plays_an_artist_twice_in_a_row_when_avoidable() {
+        let artists = "AAAAABBBCC";
+        for seed in 0..50 {
+            let order = spread(tracks_by(artists), &mut StdRng::seed_from_u64(seed));
+            let played = artists_of(&order, artists);
+            let repeats = played.as_bytes().windows(2).filter(|w| w[0] == w[1]);
+            assert_eq!(repeats.count(), 0, "seed {} played {}", seed, played);
+        }
+    }
+
+    #[test]
+    fn bunches_up_only_what_cannot_be_spread() {
+        let artists = "AAAAB";
+        let order = spread(tracks_by(artists), &mut StdRng::seed_from_u64(3));
+        let played = artists_of(&order, artists);
+        let repeats = played
+            .as_bytes()
+            .windows(2)
+            .filter(|w| w[0] == w[1])
+            .count();
+        assert_eq!(repeats, 2);
+    }
+}
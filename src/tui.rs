@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use futures::future::join_all;
+use librespot::core::spotify_id::SpotifyId;
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use crate::config as Config;
+use crate::interact as Interact;
+use crate::invoke::{Invoker, TrackCollection};
+use crate::play::{self as Play, PlayerStatus, TrackData};
+
+// How long to wait for a key press before refreshing the player status
+const TICK: Duration = Duration::from_millis(250);
+// Track rows resolved per tick so browsing a big playlist stays responsive
+const RESOLVE_BATCH: usize = 5;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Pane {
+    Library,
+    Tracks,
+}
+
+#[derive(Clone, Copy)]
+enum CollectionKind {
+    Playlist,
+    Album,
+}
+
+struct LibraryEntry {
+    kind: CollectionKind,
+    name: String,
+}
+
+struct App {
+    library: Vec<LibraryEntry>,
+    library_state: ListState,
+    tracks: Vec<SpotifyId>,
+    tracks_state: ListState,
+    tracks_height: usize,
+    // `None` once a track's metadata has failed to resolve
+    resolved: HashMap<SpotifyId, Option<TrackData>>,
+    focus: Pane,
+    status: Option<PlayerStatus>,
+}
+
+/// Full-screen alternative to the REPL, driving the same `Invoker`
+pub async fn run(invoker: &mut Invoker) {
+    let mut app = App::new(invoker);
+    let mut terminal = ratatui::init();
+    loop {
+        app.status = invoker.status().await;
+        let _ = terminal.draw(|frame| app.draw(frame));
+        app.resolve_visible(invoker).await;
+
+        if !event::poll(TICK).unwrap_or(false) {
+            continue;
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break,
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => app.toggle_focus(),
+            KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1, invoker),
+            KeyCode::Down | KeyCode::Char('j') => app.move_selection(1, invoker),
+            KeyCode::Enter => app.play_selected(invoker),
+            KeyCode::Char('s') => invoker.stop().await,
+            KeyCode::Char('r') => {
+                invoker.cycle_repeat();
+            }
+            _ => (),
+        }
+    }
+    ratatui::restore();
+    invoker.quit().await;
+}
+
+impl App {
+    fn new(invoker: &Invoker) -> App {
+        let fetcher = invoker.fetcher();
+        let labelled_playlists = fetcher.playlists_by_label();
+        let mut playlists: Vec<&String> = labelled_playlists.keys().collect();
+        let mut albums: Vec<&String> = fetcher.albums().keys().collect();
+        playlists.sort();
+        albums.sort();
+        let library = playlists
+            .into_iter()
+            .map(|name| (CollectionKind::Playlist, name))
+            .chain(albums.into_iter().map(|name| (CollectionKind::Album, name)))
+            .map(|(kind, name)| LibraryEntry {
+                kind,
+                name: name.to_owned(),
+            })
+            .collect();
+
+        let mut app = App {
+            library,
+            library_state: ListState::default(),
+            tracks: Vec::new(),
+            tracks_state: ListState::default(),
+            tracks_height: 0,
+            resolved: HashMap::new(),
+            focus: Pane::Library,
+            status: None,
+        };
+        if !app.library.is_empty() {
+            app.library_state.select(Some(0));
+            app.load_tracks(invoker);
+        }
+        app
+    }
+
+    fn toggle_focus(&mut self) {
+        self.focus = match self.focus {
+            Pane::Library => Pane::Tracks,
+            Pane::Tracks => Pane::Library,
+        };
+    }
+
+    fn move_selection(&mut self, step: isize, invoker: &Invoker) {
+        let (state, len) = match self.focus {
+            Pane::Library => (&mut self.library_state, self.library.len()),
+            Pane::Tracks => (&mut self.tracks_state, self.tracks.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let selected = state.selected().unwrap_or(0) as isize;
+        state.select(Some((selected + step).clamp(0, len as isize - 1) as usize));
+        if self.focus == Pane::Library {
+            self.load_tracks(invoker);
+        }
+    }
+
+    /// Shows the tracks of the collection selected in the library pane
+    fn load_tracks(&mut self, invoker: &Invoker) {
+        let fetcher = invoker.fetcher();
+        let selected = self.library_state.selected().map(|i| &self.library[i]);
+        self.tracks = match selected {
+            Some(entry) => match entry.kind {
+                CollectionKind::Playlist => fetcher
+                    .playlists_by_label()
+                    .get(&entry.name)
+                    .map(|p| p.tracks()),
+                CollectionKind::Album => fetcher.albums().get(&entry.name).map(|a| a.tracks()),
+            }
+            .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        self.tracks_state = ListState::default();
+        if !self.tracks.is_empty() {
+            self.tracks_state.select(Some(0));
+        }
+    }
+
+    fn play_selected(&self, invoker: &Invoker) {
+        let start = match self.focus {
+            Pane::Library => 0,
+            Pane::Tracks => self.tracks_state.selected().unwrap_or(0),
+        };
+        let source = match self.library_state.selected() {
+            Some(index) => self.library[index].name.to_owned(),
+            None => return,
+        };
+        if start < self.tracks.len() {
+            invoker.start_playback(self.tracks.clone(), start, source);
+        }
+    }
+
+    /// Fetches metadata for the track rows currently on screen
+    async fn resolve_visible(&mut self, invoker: &Invoker) {
+        let missing: Vec<SpotifyId> = self
+            .tracks
+            .iter()
+            .skip(self.tracks_state.offset())
+            .take(self.tracks_height)
+            .filter(|track_id| !self.resolved.contains_key(track_id))
+            .take(RESOLVE_BATCH)
+            .copied()
+            .collect();
+        let session = invoker.session();
+        let fetched = join_all(
+            missing
+                .iter()
+                .map(|track_id| Play::fetch_track_data(session, *track_id)),
+        )
+        .await;
+        self.resolved.extend(missing.into_iter().zip(fetched));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main_area, now_playing_area, help_area] = Layout::vertical([
+            Constraint::Min(3),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [library_area, tracks_area, queue_area] = Layout::horizontal([
+            Constraint::Percentage(25),
+            Constraint::Percentage(45),
+            Constraint::Percentage(30),
+        ])
+        .areas(main_area);
+
+        let library_items: Vec<ListItem> = self
+            .library
+            .iter()
+            .map(|entry| {
+                let prefix = match entry.kind {
+                    CollectionKind::Playlist => "♫ ",
+                    CollectionKind::Album => "◎ ",
+                };
+                ListItem::new(prefix.to_owned() + &entry.name)
+            })
+            .collect();
+        let library = List::new(library_items)
+            .block(pane_block("Library", self.focus == Pane::Library))
+            .highlight_style(highlight_style());
+        frame.render_stateful_widget(library, library_area, &mut self.library_state);
+
+        let track_items: Vec<ListItem> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track_id)| {
+                let name = match self.resolved.get(track_id) {
+                    Some(Some(track_data)) => Play::track_name(track_data),
+                    Some(None) => String::from("(unavailable)"),
+                    None => String::from("..."),
+                };
+                ListItem::new(format!("{:>3}. {}", index + 1, name))
+            })
+            .collect();
+        let tracks = List::new(track_items)
+            .block(pane_block("Tracks", self.focus == Pane::Tracks))
+            .highlight_style(highlight_style());
+        self.tracks_height = tracks_area.height.saturating_sub(2) as usize;
+        frame.render_stateful_widget(tracks, tracks_area, &mut self.tracks_state);
+
+        let mut queue_items: Vec<ListItem> = Vec::new();
+        let mut now_playing = String::from("Nothing playing");
+        if let Some(status) = &self.status {
+            if let Some(current) = &status.current {
+                let name = Play::track_name(current);
+                queue_items.push(ListItem::new(format!("▶ {}", name)).style(highlight_style()));
+                now_playing = format!(
+                    "▶ {}    {} / {}",
+                    name,
+                    Interact::format_duration(status.position_ms as i32),
+                    Interact::format_duration(current.track.duration)
+                );
+            }
+            for track_data in &status.upcoming {
+                queue_items.push(ListItem::new(Play::track_name(track_data)));
+            }
+            let unresolved = status.queue_length.saturating_sub(status.upcoming.len());
+            if unresolved > 0 {
+                queue_items.push(ListItem::new(format!("+ {} more", unresolved)));
+            }
+            now_playing += &format!("    repeat: {}", status.repeat);
+        }
+        let queue = List::new(queue_items).block(pane_block("Queue", false));
+        frame.render_widget(queue, queue_area);
+
+        let now_playing = Paragraph::new(now_playing)
+            .style(Style::default().fg(accent_color()))
+            .block(Block::bordered().title("Now Playing"));
+        frame.render_widget(now_playing, now_playing_area);
+
+        let help =
+            Paragraph::new("Tab switch pane · ↑/↓ move · Enter play · s stop · r repeat · q quit")
+                .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(help, help_area);
+    }
+}
+
+/// The `theme.accent` colour, which is always one ratatui knows by name
+fn accent_color() -> Color {
+    Color::from_str(&Config::settings().theme_accent).unwrap_or(Color::Green)
+}
+
+fn pane_block(title: &str, focused: bool) -> Block<'_> {
+    let border_color = match focused {
+        true => accent_color(),
+        false => Color::DarkGray,
+    };
+    Block::bordered()
+        .title(title)
+        .border_style(Style::default().fg(border_color))
+}
+
+fn highlight_style() -> Style {
+    Style::default()
+        .fg(accent_color())
+        .add_modifier(Modifier::BOLD)
+}